@@ -0,0 +1,135 @@
+//! Checks that `encryption` builds and works with only `core`: no `std`, and no allocator.
+//!
+//! This is a `no_std` binary for Linux that uses the C runtime for startup and `abort`, so it can
+//! run on the build machine. It exits with 0 if every check passes, or the number of the first
+//! failing check:
+//!
+//! ```text
+//! cargo run --manifest-path no_std_test/Cargo.toml
+//! ```
+
+#![no_std]
+#![no_main]
+
+use core::ffi::{c_char, c_int};
+use core::panic::PanicInfo;
+
+use encryption::alg1::{Algorithm1, CipherBlock, IndexedBlock};
+use encryption::chacha::ChaCha20;
+use encryption::{
+    cipher_message, kat, AlgorithmId, DynBlockCipher, Hardened, Header, HeaderPermutation, Key,
+    Keyring, Tag, WordMode, HEADER_SIZE,
+};
+
+// Link the C library for `_start`, `main`'s caller and `abort`
+#[link(name = "c")]
+extern "C" {
+    fn abort() -> !;
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    // SAFETY: `abort` has no preconditions
+    unsafe { abort() }
+}
+
+/// The precompiled `core` refers to the unwinding personality routine even though this binary
+/// aborts on panic, so it is never called
+#[no_mangle]
+extern "C" fn rust_eh_personality() {}
+
+/// Not a real key! Only for exercising the ciphers
+static KEY: Key<256> = Key::new(key_bytes());
+
+const fn key_bytes() -> [u8; 256] {
+    let mut bytes = [0u8; 256];
+    let mut i = 0;
+    while i < bytes.len() {
+        bytes[i] = (i as u8).wrapping_mul(167).wrapping_add(13);
+        i += 1;
+    }
+    bytes
+}
+
+#[no_mangle]
+pub extern "C" fn main(_argc: c_int, _argv: *const *const c_char) -> c_int {
+    let checks: [fn() -> bool; 6] = [
+        || kat::self_test().is_ok(),
+        cipher_block,
+        indexed_block,
+        keyring,
+        hardened,
+        message,
+    ];
+    for (i, check) in checks.iter().enumerate() {
+        if !check() {
+            return i as c_int + 1;
+        }
+    }
+    0
+}
+
+/// Ciphers are built at compile time, so they can be placed in flash
+static CIPHER: Algorithm1<'static, 256> =
+    Algorithm1::new(&KEY, 0x1234).with_mode(WordMode::WordXor);
+
+fn cipher_block() -> bool {
+    let plaintext = [0x5A; 28];
+    let mut block = CipherBlock::new(plaintext);
+    CIPHER.cipher_block(7, &mut block);
+    let encrypted = block.0 != plaintext;
+    CIPHER.cipher_block(7, &mut block);
+    encrypted && block.0 == plaintext
+}
+
+fn indexed_block() -> bool {
+    let cipher = ChaCha20::new(&KEY);
+    let permutation = HeaderPermutation::new(0xDEAD_BEEF);
+    let mut block = IndexedBlock::new();
+    block.tag().set_index(42);
+    block.data_mut()[3] = 99;
+    block.do_cipher(&cipher);
+    block.encode_header(&permutation);
+    let wire = block.to_wire_bytes();
+
+    let Ok(mut received) = IndexedBlock::from_wire_bytes(&wire) else {
+        return false;
+    };
+    received.decode_header(&permutation);
+    received.do_cipher(&cipher);
+    received.tag().get_index() == 42 && received.data()[3] == 99
+}
+
+fn keyring() -> bool {
+    let mut keyring = Keyring::<_, _, 2>::new();
+    if keyring.insert(1, Algorithm1::new(&KEY, 5)).is_err() {
+        return false;
+    }
+    let mut block = IndexedBlock::new();
+    block.tag().set_tag(1);
+    block.data_mut()[0] = 1;
+    let ok = block.do_cipher_keyring(&keyring).is_ok() && block.data()[0] != 1;
+    ok && block.do_cipher_keyring(&keyring).is_ok() && block.data()[0] == 1
+}
+
+fn hardened() -> bool {
+    let cipher = Hardened::new(Algorithm1::new(&KEY, 5), 0x0123_4567);
+    let mut words = [1u32, 2, 3, 4, 5, 6, 7];
+    cipher.encrypt_words(9, &mut words);
+    let encrypted = words != [1, 2, 3, 4, 5, 6, 7];
+    cipher.decrypt_words(9, &mut words);
+    encrypted && words == [1, 2, 3, 4, 5, 6, 7]
+}
+
+fn message() -> bool {
+    let alg1 = Algorithm1::new(&KEY, 5);
+    let chacha = ChaCha20::new(&KEY);
+    let ciphers: [(u8, &dyn DynBlockCipher); 2] = [(0, &alg1), (0, &chacha)];
+
+    let mut message = [0x33u8; HEADER_SIZE + 2 * 28];
+    message[..HEADER_SIZE].copy_from_slice(&Header::new(AlgorithmId::ChaCha20, 0, 3).to_bytes());
+    if cipher_message(&ciphers, &mut message).is_err() || message[HEADER_SIZE..] == [0x33; 56] {
+        return false;
+    }
+    cipher_message(&ciphers, &mut message).is_ok() && message[HEADER_SIZE..] == [0x33; 56]
+}
@@ -1,8 +1,13 @@
 //! Algorithm fon encrypting 28 byte blocks with 32 bit indices and the identity hash function
 
-use core::mem::size_of;
-
-use crate::{GenericCipher, GenericCipherBlock, Key};
+pub use crate::tag::{Tag, Tag31_1};
+use crate::{
+    AlgorithmId, BlockCipher, GenericCipher, GenericCipherBlock, Key, NoObserver, UsageObserver,
+    WordCipher, WordMode,
+};
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+use core::borrow::Borrow;
 
 const BLOCK_SIZE: usize = 28;
 const ELEMENT_COUNT: usize = 7;
@@ -13,143 +18,117 @@ fn identity_hash(index: u32) -> u32 {
     index
 }
 
-pub struct Algorithm1<'k, const KEY_SIZE: usize>(
-    GenericCipher<'k, fn(u32) -> u32, u32, KEY_SIZE, BLOCK_SIZE>,
-);
+/// Holds `key` through `K`, which defaults to a borrow for `'k`. Use [`Algorithm1::from_key`] with
+/// an owner such as `Arc<Key>` for a cipher that can outlive the scope of the key, or be moved
+/// to other threads
+pub struct Algorithm1<
+    'k,
+    const KEY_SIZE: usize,
+    O: UsageObserver = NoObserver,
+    K: Borrow<Key<KEY_SIZE>> = &'k Key<KEY_SIZE>,
+>(GenericCipher<'k, fn(u32) -> u32, u32, KEY_SIZE, BLOCK_SIZE, O, K>);
+
+/// An [`Algorithm1`] that shares ownership of its key, so it is `Send + Sync + 'static`
+#[cfg(feature = "alloc")]
+pub type SharedAlgorithm1<const KEY_SIZE: usize, O = NoObserver> =
+    Algorithm1<'static, KEY_SIZE, O, Arc<Key<KEY_SIZE>>>;
 
 impl<'k, const KEY_BYTES: usize> Algorithm1<'k, KEY_BYTES> {
-    pub fn new(key: &'k Key<KEY_BYTES>, index_key: u32) -> Self {
-        Self(GenericCipher::new(identity_hash, key, index_key))
-    }
-
-    /// Encrypts or decrypts a single block using `key` and `index`.
-    /// Because Xor is used, the encryption and decryption operation is the same
-    pub fn cipher_block(&self, index: u32, block: &mut GenericCipherBlock<BLOCK_SIZE>) {
-        self.0
-            .cipher_block::<ELEMENT_COUNT, 4, u32>(index, block.into())
+    pub const fn new(key: &'k Key<KEY_BYTES>, index_key: u32) -> Self {
+        Self::with_observer(key, index_key, NoObserver)
     }
 }
 
-/// High level index block for storing index and encrypted data togther, optimized for 32 bytes
-/// messages
-#[repr(C, align(4))]
-#[derive(Default)]
-pub struct IndexedBlock {
-    tag: Tag31_1,
-    data: [u32; ELEMENT_COUNT],
-}
-
-impl IndexedBlock {
-    pub fn new() -> Self {
-        Self {
-            tag: Tag31_1::new(0),
-            data: [0; ELEMENT_COUNT],
-        }
-    }
-
-    pub fn data(&self) -> &[u32; ELEMENT_COUNT] {
-        &self.data
+impl<const KEY_BYTES: usize, K: Borrow<Key<KEY_BYTES>>> Algorithm1<'_, KEY_BYTES, NoObserver, K> {
+    /// Creates a cipher that holds `key` by value, for example an `Arc<Key>` shared with other
+    /// ciphers
+    pub const fn from_key(key: K, index_key: u32) -> Self {
+        Self::with_observer(key, index_key, NoObserver)
     }
+}
 
-    pub fn data_mut(&mut self) -> &mut [u32; ELEMENT_COUNT] {
-        &mut self.data
+impl<const KEY_BYTES: usize, O: UsageObserver, K: Borrow<Key<KEY_BYTES>>>
+    Algorithm1<'_, KEY_BYTES, O, K>
+{
+    /// Creates a cipher that reports which part of `key` is used for each block to `observer`
+    pub const fn with_observer(key: K, index_key: u32, observer: O) -> Self {
+        Self(GenericCipher::with_observer(
+            identity_hash,
+            key,
+            index_key,
+            observer,
+        ))
     }
 
-    pub fn tag(&mut self) -> &mut Tag31_1 {
-        &mut self.tag
+    /// Sets how the keystream is read from the key. Defaults to [`WordMode::COMPILED`], so
+    /// ciphers in each mode can be used together in one build
+    pub const fn with_mode(mut self, mode: WordMode) -> Self {
+        self.0.set_mode(mode);
+        self
     }
 
-    /// Returns this entire message as a byte slice, sutiable for transmitting
-    pub fn as_bytes(&self) -> &[u8] {
-        let this: *const Self = self;
-        let ptr: *const u8 = this as *const u8;
-        unsafe { core::slice::from_raw_parts(ptr, size_of::<Self>()) }
+    /// Returns how the keystream is read from the key
+    pub const fn mode(&self) -> WordMode {
+        self.0.mode()
     }
 
-    /// Returns this entire message as a byte slice, sutiable for reciving
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        let this: *mut Self = self;
-        let ptr: *mut u8 = this as *mut u8;
-        unsafe { core::slice::from_raw_parts_mut(ptr, size_of::<Self>()) }
+    /// Returns the number of distinct word offsets into the key that blocks can start at.
+    /// Useful for sizing a [`UsageCounter`](crate::UsageCounter)
+    pub fn offset_count(&self) -> usize {
+        self.0.offset_count::<ELEMENT_COUNT, u32>()
     }
 
-    pub fn do_cipher<const KEY_SIZE: usize>(&mut self, cipher: &Algorithm1<'_, KEY_SIZE>) {
-        let index = Tag::get_index(self.tag());
-        let data: &mut [u32; ELEMENT_COUNT] = &mut self.data;
-
-        //SAFETY:
-        // 1. size_of([u32; ELEMENT_COUNT]) is BLOCK_SIZE so we are transmuting to a pointer with the same length
-        // 2. u8 can have any alignment
-        // 3. The last readable index is in range of the same allocated object by the math above
-        let data: &mut [u8; BLOCK_SIZE] = unsafe { core::mem::transmute(data) };
-        let block = crate::algorithm::CipherBlockRef::new(data);
-        cipher.0.cipher_block::<ELEMENT_COUNT, 4, u32>(index, block)
+    /// Encrypts or decrypts a single block using `key` and `index`.
+    /// Because Xor is used, the encryption and decryption operation is the same
+    pub fn cipher_block(&self, index: u32, block: &mut GenericCipherBlock<BLOCK_SIZE>) {
+        self.0
+            .cipher_block::<ELEMENT_COUNT, 4, u32>(index, block.into())
     }
 }
 
-/// Represents the header bits of a message that contain the index and other user specified data
-pub trait Tag {
-    type IndexTy: crate::Index;
-
-    /// Creates a new Tag. The intex bits will be initally set to zero
-    fn new(index: Self::IndexTy) -> Self;
-
-    /// Returns the index of this tag with the tag part removed
-    fn get_index(&self) -> Self::IndexTy;
-
-    fn set_index(&mut self, index: Self::IndexTy);
-
-    /// Returns the tag bits
-    fn get_tag(&self) -> usize;
-
-    /// Sets the tag bits stored in this tag
-    /// Only the lowest [`tag_bits_count`] bits will be stored and be available with [`get_tag`]
-    /// the higher bits will be discarded
-    fn set_tag(&mut self, tag: usize);
-
-    /// Returns the number of tag bits this tag supports
-    fn tag_bits_count() -> usize;
-}
-
-pub(crate) const INDEX_MASK_31: u32 = 0x7FFF_FFFF;
-const TAG_31_BITS_OFFSET: u32 = 31;
-
-#[derive(Default)]
-#[repr(transparent)]
-pub struct Tag31_1(u32);
+/// High level index block for storing index and encrypted data togther, optimized for 32 bytes
+/// messages
+pub type IndexedBlock = crate::GenericIndexedBlock<Tag31_1, u32, ELEMENT_COUNT>;
 
-impl Tag for Tag31_1 {
-    type IndexTy = u32;
+/// An [`IndexedBlock`] followed by a CRC-32, for detecting corruption before decrypting
+pub type ChecksummedBlock = crate::ChecksummedBlock<Tag31_1, u32, ELEMENT_COUNT>;
 
-    fn new(index: Self::IndexTy) -> Self {
-        Tag31_1(index)
-    }
+impl<const KEY_SIZE: usize, O: UsageObserver, K: Borrow<Key<KEY_SIZE>>>
+    WordCipher<u32, ELEMENT_COUNT> for Algorithm1<'_, KEY_SIZE, O, K>
+{
+    type Index = u32;
 
-    fn get_index(&self) -> Self::IndexTy {
-        self.0 & INDEX_MASK_31
+    fn cipher_words(&self, index: u32, words: &mut [u32; ELEMENT_COUNT]) {
+        self.0.cipher_words::<ELEMENT_COUNT, u32>(index, words)
     }
+}
 
-    fn set_index(&mut self, index: Self::IndexTy) {
-        self.0 = (index & INDEX_MASK_31) | (self.0 & !INDEX_MASK_31);
-    }
+impl<const KEY_SIZE: usize, O: UsageObserver, K: Borrow<Key<KEY_SIZE>>> BlockCipher
+    for Algorithm1<'_, KEY_SIZE, O, K>
+{
+    const ID: AlgorithmId = AlgorithmId::Alg1;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const INDEXED_BLOCK_SIZE: usize = IndexedBlock::WIRE_SIZE;
 
-    fn get_tag(&self) -> usize {
-        ((self.0 & !INDEX_MASK_31) >> TAG_31_BITS_OFFSET) as usize
-    }
+    type Index = u32;
+    type Block = CipherBlock;
+    type IndexedBlock = IndexedBlock;
 
-    fn set_tag(&mut self, tag: usize) {
-        self.0 = (self.0 & INDEX_MASK_31) | (tag << TAG_31_BITS_OFFSET) as u32
+    fn word_mode(&self) -> Option<WordMode> {
+        Some(self.mode())
     }
 
-    fn tag_bits_count() -> usize {
-        1
+    fn cipher_block(&self, index: u32, block: &mut CipherBlock) {
+        Algorithm1::cipher_block(self, index, block)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{HeaderPermutation, Keyring, KeyringError, WireError};
     use rand::{RngCore, SeedableRng};
+    use std::vec::Vec;
 
     #[test]
     fn encrypt_and_decrypt_basic() {
@@ -177,14 +156,12 @@ mod tests {
             cipher.cipher_block(index, &mut block);
             assert_eq!(block.as_ref(), original_block.as_ref());
         }
-
-        crate::key::print_freq();
     }
 
     #[test]
     fn encrypt_and_decrypt() {
         let mut i = 0;
-        for _ in 0..100 {
+        for _ in (0..100).step_by(crate::test_step(10)) {
             let mut rng = rand::rngs::StdRng::seed_from_u64(i);
             let mut index_key = [0u8; 4];
             rng.fill_bytes(&mut index_key);
@@ -195,7 +172,7 @@ mod tests {
             let key = Key::new(key_bytes);
             let cipher = Algorithm1::new(&key, index_key);
 
-            for _ in 0..100 {
+            for _ in (0..100).step_by(crate::test_step(10)) {
                 let mut block = IndexedBlock::new();
                 rng.fill_bytes(block.as_bytes_mut());
                 block.tag().set_index(i as u32);
@@ -208,33 +185,129 @@ mod tests {
                 i += 1;
             }
         }
-
-        crate::key::print_freq();
     }
 
     #[test]
-    fn tag() {
-        let mut tag: Tag31_1 = Tag::new(0);
-        assert_eq!(Tag31_1::tag_bits_count(), 1);
-        assert_eq!(tag.get_index(), 0);
-        assert_eq!(tag.get_tag(), 0);
+    fn keyring_rotation() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut old_key_bytes = [0u8; 64];
+        rng.fill_bytes(&mut old_key_bytes);
+        let old_key = Key::new(old_key_bytes);
+        let mut new_key_bytes = [0u8; 64];
+        rng.fill_bytes(&mut new_key_bytes);
+        let new_key = Key::new(new_key_bytes);
+
+        let mut keyring: Keyring<_, _, 2> = Keyring::new();
+        keyring.insert(0, Algorithm1::new(&old_key, 1)).unwrap();
+        keyring.insert(1, Algorithm1::new(&new_key, 2)).unwrap();
+
+        for key_id in 0..2 {
+            let mut block = IndexedBlock::new();
+            rng.fill_bytes(block.as_bytes_mut());
+            block.tag().set_index(42);
+            block.tag().set_tag(key_id);
+            let original_block = block.data().to_vec();
+
+            block.do_cipher_keyring(&keyring).unwrap();
+            let mut expected = IndexedBlock::new();
+            expected.data_mut().copy_from_slice(&original_block);
+            expected.tag().set_index(42);
+            expected.do_cipher(keyring.get(key_id).unwrap());
+            assert_eq!(expected.data(), block.data());
+
+            block.do_cipher_keyring(&keyring).unwrap();
+            assert_eq!(&original_block, block.data().as_slice());
+        }
 
-        tag.set_tag(1);
-        assert_eq!(tag.get_tag(), 1);
+        keyring.retire(0).unwrap();
+        let mut block = IndexedBlock::new();
+        block.tag().set_tag(0);
+        assert_eq!(
+            block.do_cipher_keyring(&keyring),
+            Err(KeyringError::UnknownKeyId(0))
+        );
+        assert_eq!(block.data(), &[0; ELEMENT_COUNT]);
+    }
 
-        tag.set_tag(0);
-        assert_eq!(tag.get_tag(), 0);
+    #[test]
+    fn wire_format() {
+        let mut block = IndexedBlock::new();
+        block.tag().set_index(0x0102_0304);
+        block.tag().set_tag(1);
+        for (i, word) in block.data_mut().iter_mut().enumerate() {
+            *word = 0x1020_3040 + i as u32;
+        }
 
-        tag.set_tag(3);
-        assert_eq!(tag.get_tag(), 1);
+        #[rustfmt::skip]
+        let expected = [
+            0x04, 0x03, 0x02, 0x81,
+            0x40, 0x30, 0x20, 0x10,
+            0x41, 0x30, 0x20, 0x10,
+            0x42, 0x30, 0x20, 0x10,
+            0x43, 0x30, 0x20, 0x10,
+            0x44, 0x30, 0x20, 0x10,
+            0x45, 0x30, 0x20, 0x10,
+            0x46, 0x30, 0x20, 0x10,
+        ];
+        let wire = block.to_wire_bytes();
+        assert_eq!(wire, expected);
+        #[cfg(target_endian = "little")]
+        assert_eq!(wire[..], *block.as_bytes());
+
+        let mut parsed = IndexedBlock::from_wire_bytes(&wire).unwrap();
+        assert_eq!(parsed.tag().get_index(), 0x0102_0304);
+        assert_eq!(parsed.tag().get_tag(), 1);
+        assert_eq!(parsed.data(), block.data());
+
+        assert_eq!(
+            IndexedBlock::from_wire_bytes(&wire[..31]).err(),
+            Some(WireError::WrongLength {
+                expected: 32,
+                actual: 31
+            })
+        );
+        assert_eq!(
+            IndexedBlock::from_wire_bytes(&[0; 33]).err(),
+            Some(WireError::WrongLength {
+                expected: 32,
+                actual: 33
+            })
+        );
+        assert!(IndexedBlock::from_wire_bytes(&[]).is_err());
+    }
 
-        tag.set_index(0xFFFF_FFFF);
-        assert_eq!(tag.get_index(), INDEX_MASK_31);
-        assert_eq!(tag.get_tag(), 1);
+    #[test]
+    fn encrypt_over_wire() {
+        let key = Key::new([0xA5u8; 128]);
+        let cipher = Algorithm1::new(&key, 0x1234_5678);
+
+        let mut block = IndexedBlock::new();
+        block.data_mut().copy_from_slice(&[1, 2, 3, 4, 5, 6, 7]);
+        block.tag().set_index(99);
+        block.do_cipher(&cipher);
+
+        let mut received = IndexedBlock::from_wire_bytes(&block.to_wire_bytes()).unwrap();
+        received.do_cipher(&cipher);
+        assert_eq!(received.data(), &[1, 2, 3, 4, 5, 6, 7]);
+    }
 
-        tag.set_index(0);
-        assert_eq!(tag.get_index(), 0);
-        assert_eq!(tag.get_tag(), 1);
+    #[test]
+    fn encoded_header() {
+        let permutation = HeaderPermutation::new(9);
+        let mut block = IndexedBlock::new();
+        for index in 0..1000 {
+            block.tag().set_index(index);
+            block.tag().set_tag(index as usize & 1);
+            block.encode_header(&permutation);
+            let wire = block.to_wire_bytes();
+            assert_ne!(wire[..4], (((index & 1) << 31) | index).to_le_bytes());
+
+            let mut received = IndexedBlock::from_wire_bytes(&wire).unwrap();
+            received.decode_header(&permutation);
+            assert_eq!(received.tag().get_index(), index);
+            assert_eq!(received.tag().get_tag(), index as usize & 1);
+            block.decode_header(&permutation);
+        }
     }
 
     #[test]
@@ -244,4 +317,95 @@ mod tests {
         assert_eq!(size_of::<IndexedBlock>(), 32);
         assert_eq!(align_of::<IndexedBlock>(), 4);
     }
+
+    #[test]
+    fn keystream_is_little_endian() {
+        // In word mode the keystream of a block is a run of key bytes read as little endian words.
+        // A big endian target reading the key natively would see every word byte swapped
+        let bytes: [u8; 64] = core::array::from_fn(|i| i as u8);
+        let key = Key::new(bytes);
+        let cipher = Algorithm1::new(&key, 0).with_mode(WordMode::WordXor);
+        // 16 key words give 10 subkey offsets, so index 3 starts at key word 3
+        let mut words = [0u32; ELEMENT_COUNT];
+        cipher.cipher_words(3, &mut words);
+        let expected: Vec<u32> = bytes[12..40]
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(words[..], expected[..]);
+
+        // So blocks of bytes are Xored with the key bytes themselves
+        let mut block = CipherBlock::new([0; BLOCK_SIZE]);
+        cipher.cipher_block(3, &mut block);
+        assert_eq!(block.0[..], bytes[12..40]);
+
+        // Byte swapping the key view byte swaps every keystream word
+        let mut swapped = bytes;
+        for word in swapped.chunks_exact_mut(4) {
+            word.reverse();
+        }
+        let swapped = Key::new(swapped);
+        let mut swapped_words = [0u32; ELEMENT_COUNT];
+        Algorithm1::new(&swapped, 0)
+            .with_mode(WordMode::WordXor)
+            .cipher_words(3, &mut swapped_words);
+        assert_eq!(swapped_words, words.map(u32::swap_bytes));
+    }
+
+    #[test]
+    fn word_modes() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let mut key_bytes = [0u8; 256];
+        rng.fill_bytes(&mut key_bytes);
+        let key = Key::new(key_bytes);
+
+        let default = Algorithm1::new(&key, 3);
+        assert_eq!(default.mode(), WordMode::COMPILED);
+        let word_xor = Algorithm1::new(&key, 3).with_mode(WordMode::WordXor);
+        let bit_offset = Algorithm1::new(&key, 3).with_mode(WordMode::BitOffset);
+        assert_eq!(word_xor.offset_count(), bit_offset.offset_count() + 1);
+
+        // Both modes work in one build, and each only decrypts its own output
+        let original = [0x5Au8; BLOCK_SIZE];
+        for index in 0..100 {
+            let mut a = CipherBlock::new(original);
+            let mut b = CipherBlock::new(original);
+            word_xor.cipher_block(index, &mut a);
+            bit_offset.cipher_block(index, &mut b);
+            assert_ne!(a.0, b.0);
+
+            let mut c = CipherBlock::new(a.0);
+            bit_offset.cipher_block(index, &mut c);
+            assert_ne!(c.0, original);
+            word_xor.cipher_block(index, &mut a);
+            bit_offset.cipher_block(index, &mut b);
+            assert_eq!(a.0, original);
+            assert_eq!(b.0, original);
+        }
+    }
+
+    /// Built at compile time, so it can be placed in flash next to `KEY` on embedded targets
+    static CIPHER: Algorithm1<'static, 53280> =
+        Algorithm1::new(&crate::KEY, 0x1234).with_mode(WordMode::WordXor);
+
+    #[test]
+    fn static_cipher() {
+        let runtime = Algorithm1::new(&crate::KEY, 0x1234).with_mode(WordMode::WordXor);
+        assert_eq!(CIPHER.mode(), WordMode::WordXor);
+        for index in 0..100 {
+            let mut a = CipherBlock::new([index as u8; BLOCK_SIZE]);
+            let mut b = CipherBlock::new([index as u8; BLOCK_SIZE]);
+            CIPHER.cipher_block(index, &mut a);
+            runtime.cipher_block(index, &mut b);
+            assert_eq!(a.0, b.0);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn shared_key() {
+        let key = Arc::new(Key::new([0x42u8; 512]));
+        let shared: SharedAlgorithm1<512> = Algorithm1::from_key(key.clone(), 0x1234);
+        crate::cipher::tests::shared_key(shared, &Algorithm1::new(&key, 0x1234));
+    }
 }
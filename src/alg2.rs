@@ -1,7 +1,7 @@
 //! Algorithm fon encrypting 248 byte blocks with 64 bit indices, hashed by passing each byte in
 //! the index through the AES S-BOX
 
-const S_BOX: [u8; 256] = [
+pub(crate) const S_BOX: [u8; 256] = [
     0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
     0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
     0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
@@ -20,9 +20,13 @@ const S_BOX: [u8; 256] = [
     0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
 ];
 
-use core::mem::size_of;
-
-use crate::{GenericCipher, GenericCipherBlock, Key};
+use crate::{
+    AlgorithmId, Align8, BlockCipher, GenericCipher, GenericCipherBlock, Key, NoObserver, Tag56_8,
+    UsageObserver, WordCipher, WordMode,
+};
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+use core::borrow::Borrow;
 
 const BLOCK_SIZE: usize = 248;
 const ELEMENT_COUNT: usize = 31;
@@ -30,18 +34,64 @@ const ELEMENT_COUNT: usize = 31;
 pub type CipherBlock = GenericCipherBlock<BLOCK_SIZE>;
 
 /// Passes each byte of `index` through the AES S-Box to provide a non linear hash
-fn hash(index: u64) -> u64 {
+pub fn hash(index: u64) -> u64 {
     let bytes = index.to_ne_bytes().map(|b| S_BOX[b as usize]);
     u64::from_ne_bytes(bytes)
 }
 
-pub struct Algorithm2<'k, const KEY_SIZE: usize>(
-    GenericCipher<'k, fn(u64) -> u64, u64, KEY_SIZE, BLOCK_SIZE>,
-);
+/// Holds `key` through `K`, which defaults to a borrow for `'k`. Use [`Algorithm2::from_key`] with
+/// an owner such as `Arc<Key>` for a cipher that can outlive the scope of the key, or be moved
+/// to other threads
+pub struct Algorithm2<
+    'k,
+    const KEY_SIZE: usize,
+    O: UsageObserver = NoObserver,
+    K: Borrow<Key<KEY_SIZE>> = &'k Key<KEY_SIZE>,
+>(GenericCipher<'k, fn(u64) -> u64, u64, KEY_SIZE, BLOCK_SIZE, O, K>);
+
+/// An [`Algorithm2`] that shares ownership of its key, so it is `Send + Sync + 'static`
+#[cfg(feature = "alloc")]
+pub type SharedAlgorithm2<const KEY_SIZE: usize, O = NoObserver> =
+    Algorithm2<'static, KEY_SIZE, O, Arc<Key<KEY_SIZE>>>;
 
 impl<'k, const KEY_BYTES: usize> Algorithm2<'k, KEY_BYTES> {
-    pub fn new(key: &'k Key<KEY_BYTES>, index_key: u64) -> Self {
-        Self(GenericCipher::new(hash, key, index_key))
+    pub const fn new(key: &'k Key<KEY_BYTES>, index_key: u64) -> Self {
+        Self::with_observer(key, index_key, NoObserver)
+    }
+}
+
+impl<const KEY_BYTES: usize, K: Borrow<Key<KEY_BYTES>>> Algorithm2<'_, KEY_BYTES, NoObserver, K> {
+    /// Creates a cipher that holds `key` by value, for example an `Arc<Key>` shared with other
+    /// ciphers
+    pub const fn from_key(key: K, index_key: u64) -> Self {
+        Self::with_observer(key, index_key, NoObserver)
+    }
+}
+
+impl<const KEY_BYTES: usize, O: UsageObserver, K: Borrow<Key<KEY_BYTES>>>
+    Algorithm2<'_, KEY_BYTES, O, K>
+{
+    /// Creates a cipher that reports which part of `key` is used for each block to `observer`
+    pub const fn with_observer(key: K, index_key: u64, observer: O) -> Self {
+        Self(GenericCipher::with_observer(hash, key, index_key, observer))
+    }
+
+    /// Sets how the keystream is read from the key. Defaults to [`WordMode::COMPILED`], so
+    /// ciphers in each mode can be used together in one build
+    pub const fn with_mode(mut self, mode: WordMode) -> Self {
+        self.0.set_mode(mode);
+        self
+    }
+
+    /// Returns how the keystream is read from the key
+    pub const fn mode(&self) -> WordMode {
+        self.0.mode()
+    }
+
+    /// Returns the number of distinct word offsets into the key that blocks can start at.
+    /// Useful for sizing a [`UsageCounter`](crate::UsageCounter)
+    pub fn offset_count(&self) -> usize {
+        self.0.offset_count::<ELEMENT_COUNT, u64>()
     }
 
     /// Encrypts or decrypts a single block using `key` and `index`.
@@ -51,73 +101,53 @@ impl<'k, const KEY_BYTES: usize> Algorithm2<'k, KEY_BYTES> {
     }
 }
 
-/// High level index block for storing index and encrypted data togther, optimized for 32 bytes
+/// High level index block for storing index and encrypted data togther, optimized for 256 byte
 /// messages
-#[repr(C, align(8))]
-#[derive(Default)]
-pub struct IndexedBlock {
-    index: u64,
-    data: [u64; ELEMENT_COUNT],
-}
+pub type IndexedBlock = crate::GenericIndexedBlock<Tag56_8, u64, ELEMENT_COUNT, Align8>;
 
-impl IndexedBlock {
-    pub fn new() -> Self {
-        Self {
-            index: 0,
-            data: [0; ELEMENT_COUNT],
-        }
-    }
+/// An [`IndexedBlock`] followed by a CRC-32, for detecting corruption before decrypting
+pub type ChecksummedBlock = crate::ChecksummedBlock<Tag56_8, u64, ELEMENT_COUNT, Align8>;
 
-    pub fn data(&self) -> &[u64; ELEMENT_COUNT] {
-        &self.data
-    }
+impl<const KEY_SIZE: usize, O: UsageObserver, K: Borrow<Key<KEY_SIZE>>>
+    WordCipher<u64, ELEMENT_COUNT> for Algorithm2<'_, KEY_SIZE, O, K>
+{
+    type Index = u64;
 
-    pub fn data_mut(&mut self) -> &mut [u64; ELEMENT_COUNT] {
-        &mut self.data
+    fn cipher_words(&self, index: u64, words: &mut [u64; ELEMENT_COUNT]) {
+        self.0.cipher_words::<ELEMENT_COUNT, u64>(index, words)
     }
+}
 
-    pub fn index(&mut self) -> u64 {
-        self.index
-    }
+impl<const KEY_SIZE: usize, O: UsageObserver, K: Borrow<Key<KEY_SIZE>>> BlockCipher
+    for Algorithm2<'_, KEY_SIZE, O, K>
+{
+    const ID: AlgorithmId = AlgorithmId::Alg2;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const INDEXED_BLOCK_SIZE: usize = IndexedBlock::WIRE_SIZE;
 
-    /// Returns this entire message as a byte slice, sutiable for transmitting
-    pub fn as_bytes(&self) -> &[u8] {
-        let this: *const Self = self;
-        let ptr: *const u8 = this as *const u8;
-        unsafe { core::slice::from_raw_parts(ptr, size_of::<Self>()) }
-    }
+    type Index = u64;
+    type Block = CipherBlock;
+    type IndexedBlock = IndexedBlock;
 
-    /// Returns this entire message as a byte slice, sutiable for reciving
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        let this: *mut Self = self;
-        let ptr: *mut u8 = this as *mut u8;
-        unsafe { core::slice::from_raw_parts_mut(ptr, size_of::<Self>()) }
+    fn word_mode(&self) -> Option<WordMode> {
+        Some(self.mode())
     }
 
-    pub fn do_cipher<Hash, const KEY_SIZE: usize>(&mut self, cipher: &Algorithm2<'_, KEY_SIZE>) {
-        let data: &mut [u64; ELEMENT_COUNT] = &mut self.data;
-
-        //SAFETY:
-        // 1. size_of([u32; 7]) is 28 so we are transmuting to a pointer with the same length
-        // 2. u8 can have any alignment
-        // 3. The last readable index is in range of the same allocated object by the math above
-        let data: &mut [u8; BLOCK_SIZE] = unsafe { core::mem::transmute(data) };
-        let block = crate::algorithm::CipherBlockRef::new(data);
-        cipher
-            .0
-            .cipher_block::<ELEMENT_COUNT, 8, u64>(self.index, block)
+    fn cipher_block(&self, index: u64, block: &mut CipherBlock) {
+        Algorithm2::cipher_block(self, index, block)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{HeaderPermutation, Keyring, KeyringError, Tag, WireError};
     use rand::{RngCore, SeedableRng};
 
     #[test]
     fn encrypt_and_decrypt_basic() {
         let mut i = 0;
-        for _ in 0..100 {
+        for _ in (0..100).step_by(crate::test_step(10)) {
             let mut rng = rand::rngs::StdRng::seed_from_u64(i);
 
             let mut block_bytes = [0u8; 248];
@@ -135,7 +165,7 @@ mod tests {
             let mut block = CipherBlock::new(block_bytes);
             let cipher = Algorithm2::new(&key, index_key);
 
-            for _ in 0..100 {
+            for _ in (0..100).step_by(crate::test_step(10)) {
                 let index = i;
                 cipher.cipher_block(index, &mut block);
                 cipher.cipher_block(index, &mut block);
@@ -143,8 +173,127 @@ mod tests {
                 i += 1;
             }
         }
+    }
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut key_bytes = [0u8; 2048];
+        rng.fill_bytes(&mut key_bytes);
+        let key = Key::new(key_bytes);
+        let cipher = Algorithm2::new(&key, rng.next_u64());
+
+        for i in 0..100u64 {
+            let mut block = IndexedBlock::new();
+            rng.fill_bytes(block.as_bytes_mut());
+            let index = i << 48 | i;
+            block.tag().set_index(index);
+            block.tag().set_tag(i as usize);
+            let original_block = block.data().to_vec();
+
+            // Must match encrypting the data directly with the index, so the tag bits do not
+            // affect the ciphertext
+            let mut expected = CipherBlock::new([0; BLOCK_SIZE]);
+            for (bytes, word) in expected.0.chunks_exact_mut(8).zip(block.data()) {
+                bytes.copy_from_slice(&word.to_le_bytes());
+            }
+            cipher.cipher_block(index, &mut expected);
+
+            block.do_cipher(&cipher);
+            assert_eq!(block.index(), index);
+            assert_eq!(block.tag().get_tag(), i as usize);
+            assert_eq!(&block.to_wire_bytes()[8..], expected.as_ref());
+
+            block.do_cipher(&cipher);
+            assert_eq!(&original_block, block.data().as_slice());
+        }
+    }
+
+    #[test]
+    fn keyring() {
+        let old_key = Key::new([1u8; 512]);
+        let new_key = Key::new([2u8; 512]);
+        let mut keyring: Keyring<_, _, 2> = Keyring::new();
+        keyring.insert(200, Algorithm2::new(&old_key, 0)).unwrap();
+        keyring.insert(201, Algorithm2::new(&new_key, 0)).unwrap();
 
-        crate::key::print_freq();
+        let mut block = IndexedBlock::new();
+        block.tag().set_index(5);
+        block.tag().set_tag(201);
+        block.do_cipher_keyring(&keyring).unwrap();
+
+        let mut expected = IndexedBlock::new();
+        expected.tag().set_index(5);
+        expected.do_cipher(keyring.get(201).unwrap());
+        assert_eq!(block.data(), expected.data());
+
+        block.tag().set_tag(7);
+        assert_eq!(
+            block.do_cipher_keyring(&keyring),
+            Err(KeyringError::UnknownKeyId(7))
+        );
+    }
+
+    #[test]
+    fn wire_format() {
+        let mut block = IndexedBlock::new();
+        block.tag().set_index(0x0001_0203_0405_0607);
+        block.tag().set_tag(0xAB);
+        for (i, word) in block.data_mut().iter_mut().enumerate() {
+            *word = 0x1122_3344_5566_7700 + i as u64;
+        }
+
+        let wire = block.to_wire_bytes();
+        assert_eq!(
+            wire[..24],
+            [
+                0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0xAB, // tag
+                0x00, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // data[0]
+                0x01, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // data[1]
+            ]
+        );
+        assert_eq!(
+            wire[248..],
+            [0x1E, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11] // data[30]
+        );
+        #[cfg(target_endian = "little")]
+        assert_eq!(wire[..], *block.as_bytes());
+
+        let mut parsed = IndexedBlock::from_wire_bytes(&wire).unwrap();
+        assert_eq!(parsed.index(), 0x0001_0203_0405_0607);
+        assert_eq!(parsed.tag().get_tag(), 0xAB);
+        assert_eq!(parsed.data(), block.data());
+
+        assert_eq!(
+            IndexedBlock::from_wire_bytes(&wire[1..]).err(),
+            Some(WireError::WrongLength {
+                expected: 256,
+                actual: 255
+            })
+        );
+    }
+
+    #[test]
+    fn encoded_header() {
+        let key = Key::new([0x3Cu8; 512]);
+        let cipher = Algorithm2::new(&key, 77);
+        let permutation = HeaderPermutation::new(0x0123_4567_89AB_CDEF);
+
+        let mut block = IndexedBlock::new();
+        block.data_mut()[0] = 42;
+        block.tag().set_index(1000);
+        block.tag().set_tag(3);
+        block.do_cipher(&cipher);
+        block.encode_header(&permutation);
+        let wire = block.to_wire_bytes();
+        assert_ne!(wire[..8], (3u64 << 56 | 1000).to_le_bytes());
+
+        let mut received = IndexedBlock::from_wire_bytes(&wire).unwrap();
+        received.decode_header(&permutation);
+        assert_eq!(received.index(), 1000);
+        assert_eq!(received.tag().get_tag(), 3);
+        received.do_cipher(&cipher);
+        assert_eq!(received.data()[0], 42);
     }
 
     #[test]
@@ -153,4 +302,24 @@ mod tests {
         assert_eq!(size_of::<IndexedBlock>(), 256);
         assert_eq!(align_of::<IndexedBlock>(), 8);
     }
+
+    #[test]
+    fn static_cipher() {
+        // The S-Box hash is stored as a function pointer, so it is const compatible too
+        static CIPHER: Algorithm2<'static, 53280> = Algorithm2::new(&crate::KEY, 99);
+        let runtime = Algorithm2::new(&crate::KEY, 99);
+        let mut a = CipherBlock::new([7; BLOCK_SIZE]);
+        let mut b = CipherBlock::new([7; BLOCK_SIZE]);
+        CIPHER.cipher_block(1 << 40, &mut a);
+        runtime.cipher_block(1 << 40, &mut b);
+        assert_eq!(a.0, b.0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn shared_key() {
+        let key = Arc::new(Key::new([0x42u8; 512]));
+        let shared: SharedAlgorithm2<512> = Algorithm2::from_key(key.clone(), 0x1234);
+        crate::cipher::tests::shared_key(shared, &Algorithm2::new(&key, 0x1234));
+    }
 }
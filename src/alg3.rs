@@ -0,0 +1,200 @@
+//! Algorithm fon encrypting 56 byte blocks with 64 bit indices, hashed by passing each byte in
+//! the index through the AES S-BOX like [`alg2`](crate::alg2).
+//!
+//! Together with its 8 byte tag an [`IndexedBlock`] is 64 bytes, the size of a cache line on most
+//! platforms, filling the gap between the 32 byte blocks of [`alg1`](crate::alg1) and the 256
+//! byte blocks of [`alg2`](crate::alg2)
+
+use crate::alg2::hash;
+use crate::{
+    AlgorithmId, Align8, BlockCipher, GenericCipher, GenericCipherBlock, Key, NoObserver, Tag56_8,
+    UsageObserver, WordCipher, WordMode,
+};
+
+const BLOCK_SIZE: usize = 56;
+const ELEMENT_COUNT: usize = 7;
+
+pub type CipherBlock = GenericCipherBlock<BLOCK_SIZE>;
+
+pub struct Algorithm3<'k, const KEY_SIZE: usize, O: UsageObserver = NoObserver>(
+    GenericCipher<'k, fn(u64) -> u64, u64, KEY_SIZE, BLOCK_SIZE, O>,
+);
+
+impl<'k, const KEY_BYTES: usize> Algorithm3<'k, KEY_BYTES> {
+    pub const fn new(key: &'k Key<KEY_BYTES>, index_key: u64) -> Self {
+        Self::with_observer(key, index_key, NoObserver)
+    }
+}
+
+impl<'k, const KEY_BYTES: usize, O: UsageObserver> Algorithm3<'k, KEY_BYTES, O> {
+    /// Creates a cipher that reports which part of `key` is used for each block to `observer`
+    pub const fn with_observer(key: &'k Key<KEY_BYTES>, index_key: u64, observer: O) -> Self {
+        Self(GenericCipher::with_observer(hash, key, index_key, observer))
+    }
+
+    /// Sets how the keystream is read from the key. Defaults to [`WordMode::COMPILED`], so
+    /// ciphers in each mode can be used together in one build
+    pub const fn with_mode(mut self, mode: WordMode) -> Self {
+        self.0.set_mode(mode);
+        self
+    }
+
+    /// Returns how the keystream is read from the key
+    pub const fn mode(&self) -> WordMode {
+        self.0.mode()
+    }
+
+    /// Returns the number of distinct word offsets into the key that blocks can start at.
+    /// Useful for sizing a [`UsageCounter`](crate::UsageCounter)
+    pub fn offset_count(&self) -> usize {
+        self.0.offset_count::<ELEMENT_COUNT, u64>()
+    }
+
+    /// Encrypts or decrypts a single block using `key` and `index`.
+    /// Because Xor is used, the encryption and decryption operation is the same
+    pub fn cipher_block(&self, index: u64, block: &mut GenericCipherBlock<BLOCK_SIZE>) {
+        self.0
+            .cipher_block::<ELEMENT_COUNT, 8, u64>(index, block.into())
+    }
+}
+
+/// High level index block for storing index and encrypted data togther, optimized for 64 byte
+/// messages
+pub type IndexedBlock = crate::GenericIndexedBlock<Tag56_8, u64, ELEMENT_COUNT, Align8>;
+
+/// An [`IndexedBlock`] followed by a CRC-32, for detecting corruption before decrypting
+pub type ChecksummedBlock = crate::ChecksummedBlock<Tag56_8, u64, ELEMENT_COUNT, Align8>;
+
+impl<const KEY_SIZE: usize, O: UsageObserver> WordCipher<u64, ELEMENT_COUNT>
+    for Algorithm3<'_, KEY_SIZE, O>
+{
+    type Index = u64;
+
+    fn cipher_words(&self, index: u64, words: &mut [u64; ELEMENT_COUNT]) {
+        self.0.cipher_words::<ELEMENT_COUNT, u64>(index, words)
+    }
+}
+
+impl<const KEY_SIZE: usize, O: UsageObserver> BlockCipher for Algorithm3<'_, KEY_SIZE, O> {
+    const ID: AlgorithmId = AlgorithmId::Alg3;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const INDEXED_BLOCK_SIZE: usize = IndexedBlock::WIRE_SIZE;
+
+    type Index = u64;
+    type Block = CipherBlock;
+    type IndexedBlock = IndexedBlock;
+
+    fn word_mode(&self) -> Option<WordMode> {
+        Some(self.mode())
+    }
+
+    fn cipher_block(&self, index: u64, block: &mut CipherBlock) {
+        Algorithm3::cipher_block(self, index, block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::tests::{check, keyring_round_trip};
+    use crate::{HeaderPermutation, Keyring, Tag, WireError};
+    use rand::{RngCore, SeedableRng};
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut key_bytes = [[0u8; 512]; 2];
+        for bytes in &mut key_bytes {
+            rng.fill_bytes(bytes);
+        }
+        let [old_key, new_key] = key_bytes.map(Key::new);
+        let mut keyring: Keyring<_, _, 2> = Keyring::new();
+        keyring
+            .insert(10, Algorithm3::new(&old_key, rng.next_u64()))
+            .unwrap();
+        keyring
+            .insert(11, Algorithm3::new(&new_key, rng.next_u64()))
+            .unwrap();
+
+        for i in (0..100u64).step_by(crate::test_step(10)) {
+            let index = i << 48 | i;
+            check(keyring.get(10).unwrap(), index, index);
+
+            let mut block = IndexedBlock::new();
+            rng.fill_bytes(block.as_bytes_mut());
+            block.tag().set_index(index);
+            block.tag().set_tag(10 + i as usize % 2);
+            keyring_round_trip(&keyring, block);
+        }
+    }
+
+    #[test]
+    fn wire_format() {
+        let mut block = IndexedBlock::new();
+        block.tag().set_index(0x0001_0203_0405_0607);
+        block.tag().set_tag(0xAB);
+        for (i, word) in block.data_mut().iter_mut().enumerate() {
+            *word = 0x1122_3344_5566_7700 + i as u64;
+        }
+
+        let wire = block.to_wire_bytes();
+        assert_eq!(
+            wire[..16],
+            [
+                0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0xAB, // tag
+                0x00, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // data[0]
+            ]
+        );
+        assert_eq!(
+            wire[56..],
+            [0x06, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11] // data[6]
+        );
+        #[cfg(target_endian = "little")]
+        assert_eq!(wire[..], *block.as_bytes());
+
+        let mut parsed = IndexedBlock::from_wire_bytes(&wire).unwrap();
+        assert_eq!(parsed.index(), 0x0001_0203_0405_0607);
+        assert_eq!(parsed.tag().get_tag(), 0xAB);
+        assert_eq!(parsed.data(), block.data());
+
+        assert_eq!(
+            IndexedBlock::from_wire_bytes(&wire[1..]).err(),
+            Some(WireError::WrongLength {
+                expected: 64,
+                actual: 63
+            })
+        );
+    }
+
+    #[test]
+    fn encoded_header() {
+        let key = Key::new([0x3Cu8; 512]);
+        let cipher = Algorithm3::new(&key, 77);
+        let permutation = HeaderPermutation::new(0x0123_4567_89AB_CDEF);
+
+        let mut block = IndexedBlock::new();
+        block.data_mut()[0] = 42;
+        block.tag().set_index(1000);
+        block.tag().set_tag(3);
+        block.do_cipher(&cipher);
+        block.encode_header(&permutation);
+        let wire = block.to_wire_bytes();
+        assert_ne!(wire[..8], (3u64 << 56 | 1000).to_le_bytes());
+
+        let mut received = IndexedBlock::from_wire_bytes(&wire).unwrap();
+        received.decode_header(&permutation);
+        assert_eq!(received.index(), 1000);
+        assert_eq!(received.tag().get_tag(), 3);
+        received.do_cipher(&cipher);
+        assert_eq!(received.data()[0], 42);
+    }
+
+    #[test]
+    fn index_block() {
+        use core::mem::{align_of, size_of};
+        // One cache line
+        assert_eq!(size_of::<IndexedBlock>(), 64);
+        assert_eq!(align_of::<IndexedBlock>(), 8);
+        assert_eq!(IndexedBlock::WIRE_SIZE, 64);
+    }
+}
@@ -1,10 +1,47 @@
 use crate::key::Key;
+use crate::usage::{NoObserver, UsageObserver};
+use core::borrow::Borrow;
 use core::marker::PhantomData;
 use core::mem::{align_of, size_of};
 use core::ops::Deref;
 
 pub trait Index: core::ops::BitXor<Output = Self> + Sized + Copy {
     fn to_usize(self) -> usize;
+
+    /// Widens this index to 64 bits without loss, so it can be reduced on any target before it
+    /// is narrowed to `usize`
+    fn to_u64(self) -> u64;
+}
+
+/// How [`GenericCipher`] reads the keystream for a block from the key.
+///
+/// Both modes are available in every build. The `word_xor` feature only changes the default,
+/// [`WordMode::COMPILED`], used by ciphers that are not given a mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum WordMode {
+    /// Keystream words start at any bit offset into the key, the default
+    BitOffset = 0,
+    /// Keystream words are whole key words, the default with the `word_xor` feature
+    WordXor = 1,
+}
+
+impl WordMode {
+    /// The default mode, selected by the `word_xor` feature
+    pub const COMPILED: Self = if cfg!(feature = "word_xor") {
+        Self::WordXor
+    } else {
+        Self::BitOffset
+    };
+
+    /// Returns the mode with the ID `id`, or None if it is unknown
+    pub fn from_u8(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::BitOffset),
+            1 => Some(Self::WordXor),
+            _ => None,
+        }
+    }
 }
 
 #[repr(C, align(8))]
@@ -25,32 +62,96 @@ impl<'a, const N: usize, const A: usize> CipherBlockRef<'a, N, A> {
     }
 }
 
-pub struct GenericCipher<'k, Hash, IndexTy, const KEY_BYTES: usize, const BLOCK_BYTES: usize>
-where
+/// `Observer` is notified of the key offset used for each block. See [`UsageObserver`]
+///
+/// `K` holds the key. It defaults to a borrow for `'k`, but any owner such as `Arc<Key>` can be
+/// used so the cipher is not tied to the lifetime of the key
+pub struct GenericCipher<
+    'k,
+    Hash,
+    IndexTy,
+    const KEY_BYTES: usize,
+    const BLOCK_BYTES: usize,
+    Observer = NoObserver,
+    K = &'k Key<KEY_BYTES>,
+> where
     Hash: Fn(IndexTy) -> IndexTy,
     IndexTy: Index,
+    Observer: UsageObserver,
+    K: Borrow<Key<KEY_BYTES>>,
 {
     hash: Hash,
-    key: &'k Key<KEY_BYTES>,
+    key: K,
     index_key: IndexTy,
-    _index: PhantomData<IndexTy>,
+    observer: Observer,
+    mode: WordMode,
+    _index: PhantomData<(&'k (), IndexTy)>,
+}
+
+impl<'k, Hash, IndexTy, const KEY_BYTES: usize, const BLOCK_BYTES: usize, K>
+    GenericCipher<'k, Hash, IndexTy, KEY_BYTES, BLOCK_BYTES, NoObserver, K>
+where
+    Hash: Fn(IndexTy) -> IndexTy,
+    IndexTy: Index,
+    K: Borrow<Key<KEY_BYTES>>,
+{
+    pub const fn new(hash: Hash, key: K, index_key: IndexTy) -> Self {
+        Self::with_observer(hash, key, index_key, NoObserver)
+    }
 }
 
-impl<'k, Hash, IndexTy, const KEY_BYTES: usize, const BLOCK_BYTES: usize>
-    GenericCipher<'k, Hash, IndexTy, KEY_BYTES, BLOCK_BYTES>
+impl<'k, Hash, IndexTy, const KEY_BYTES: usize, const BLOCK_BYTES: usize, Observer, K>
+    GenericCipher<'k, Hash, IndexTy, KEY_BYTES, BLOCK_BYTES, Observer, K>
 where
     Hash: Fn(IndexTy) -> IndexTy,
     IndexTy: Index,
+    Observer: UsageObserver,
+    K: Borrow<Key<KEY_BYTES>>,
 {
-    pub fn new(hash: Hash, key: &'k Key<KEY_BYTES>, index_key: IndexTy) -> Self {
+    /// Creates a cipher that reports the key offset used for each block to `observer`
+    pub const fn with_observer(hash: Hash, key: K, index_key: IndexTy, observer: Observer) -> Self {
         Self {
             hash,
             key,
             index_key,
+            observer,
+            mode: WordMode::COMPILED,
             _index: PhantomData,
         }
     }
 
+    /// Sets how the keystream is read from the key. Ciphers use [`WordMode::COMPILED`] unless
+    /// this is called
+    pub const fn with_mode(mut self, mode: WordMode) -> Self {
+        self.set_mode(mode);
+        self
+    }
+
+    /// Sets how the keystream is read from the key, in place
+    pub(crate) const fn set_mode(&mut self, mode: WordMode) {
+        self.mode = mode;
+    }
+
+    /// Returns how the keystream is read from the key
+    pub const fn mode(&self) -> WordMode {
+        self.mode
+    }
+
+    /// Returns the observer of this cipher
+    pub fn observer(&self) -> &Observer {
+        &self.observer
+    }
+
+    /// Returns the number of distinct word offsets into the key that blocks of `L` words of type
+    /// `W` can start at. Every offset passed to the observer is less than this
+    pub fn offset_count<const L: usize, W: crate::Word>(&self) -> usize {
+        let key_elements = KEY_BYTES / size_of::<W>();
+        match self.mode {
+            WordMode::WordXor => key_elements + 1 - L,
+            WordMode::BitOffset => key_elements - L,
+        }
+    }
+
     /// Performs encryption or decryption of a single block.
     /// `L` determines many elements the subkey has with word size `W`. Because N is in bytes, `L` should always
     /// be set to N / `size_of::<W>()`.
@@ -68,6 +169,27 @@ where
         index: IndexTy,
         block: CipherBlockRef<BLOCK_BYTES, A>,
     ) {
+        Self::check_word_count::<L, W>();
+        // The block alignment A must be enough for the word size we picked
+        assert!(A >= align_of::<W>());
+
+        // `CipherBlockRef` always has `A` byte alignment, which is at least as large as `W`'s
+        // alignment, so the cast cannot fail
+        let buf: &mut [W] = crate::pod::cast_slice_mut(&mut block.0[..L * size_of::<W>()]);
+        let words: &mut [W; L] = buf.try_into().unwrap();
+
+        // Blocks of bytes hold little endian words like the wire format, so the ciphertext bytes
+        // are the same on every platform. These are no-ops on little endian targets
+        for word in words.iter_mut() {
+            *word = W::from_le(*word);
+        }
+        self.cipher_words::<L, W>(index, words);
+        for word in words.iter_mut() {
+            *word = word.to_le();
+        }
+    }
+
+    fn check_word_count<const L: usize, W: crate::Word>() {
         //TODO: Calculate `L` automaticaly once const generic parameters can be set to const functions
         if BLOCK_BYTES / size_of::<W>() != L {
             // User choose wrong L for N
@@ -78,105 +200,99 @@ where
                 BLOCK_BYTES / size_of::<W>()
             );
         }
-        // The word size we picked has the alignment of at least A
-        assert!(align_of::<W>() >= A);
+    }
+
+    /// Performs encryption or decryption of a single block that is already split into `L` words
+    /// of type `W`.
+    ///
+    /// Key words are read in little endian byte order, so the keystream has the same value on
+    /// every platform and blocks sent in the wire format decrypt on any receiver.
+    ///
+    /// # Panics
+    /// If `L` words of type `W` are not exactly `BLOCK_BYTES` long
+    pub fn cipher_words<const L: usize, W: crate::Word>(&self, index: IndexTy, buf: &mut [W; L]) {
+        Self::check_word_count::<L, W>();
 
         // Perform Xor first, so that an attacker doesn't know the inputs to the hash function
         let index = index ^ self.index_key;
         let index = (self.hash)(index);
-        let index = index.to_usize();
-
-        #[cfg(feature = "word_xor")]
-        {
-            let key = self.key.subkey::<W, L>(index);
-
-            // SAFETY: u8 is safe to transmute to `Word`. There are no invalid bit patterns by the impl
-            // constraints of Word (unsafe trait)
-            let (before, buf, after) = unsafe { block.0.align_to_mut::<W>() };
-
-            // These lengths are guaranteed to be 0 because `CipherBlockRef` always has `A` byte
-            // alignment, which is at least at least as large as `W`'s alignment
-            debug_assert!(before.is_empty());
-            debug_assert!(after.is_empty());
-            // Perform Xor encryption
-            for i in 0..buf.len() {
-                buf[i] ^= key[i];
-            }
+        // 64 bit indices do not fit in usize on 32 bit targets, so they are reduced as u64
+        let index = index.to_u64();
+
+        match self.mode {
+            WordMode::WordXor => self.xor_words(index, buf),
+            WordMode::BitOffset => self.xor_bit_offset(index, buf),
         }
-        #[cfg(not(feature = "word_xor"))]
-        {
-            let key = self.key.as_words::<W>();
-
-            // SAFETY: u8 is safe to transmute to `Word`. There are no invalid bit patterns by the impl
-            // constraints of Word (unsafe trait)
-            let (before, buf, after) = unsafe { block.0.align_to_mut::<W>() };
-
-            // These lengths are guaranteed to be 0 because `CipherBlockRef` always has `A` byte
-            // alignment, which is at least at least as large as `W`'s alignment
-            debug_assert!(before.is_empty());
-            debug_assert!(after.is_empty());
-
-            // The index of one past the last element we can access
-            let max_element = key.len() - buf.len();
-            let word_bits = size_of::<W>() * 8;
-            let max_bit = max_element * word_bits;
-
-            // Because of the bit offsets, we may need to strattle two extra words, so subtract the
-            // number of bytes in a word when finding offset
-            let offset = index % max_bit;
-            let mut word_offset = offset / word_bits;
-            let bit_offset = offset % word_bits;
-            //start encrypting the high bits of block starting with the key bit at `bit_offset`
-
-            dbg!(
-                key.len(),
-                buf.len(),
-                max_element,
-                max_bit,
-                offset,
-                index,
-                word_offset,
-                bit_offset
-            );
+    }
 
-            //encrypts the upper part of `block`
-            let encrypt_upper = |block_word: &mut W, key_word: W| {
-                // low bits of key are zero after bit shifting, so we can simply xor the whole thing
-                dbg!(bit_offset);
-                let key = key_word << bit_offset;
-                *block_word ^= key;
-            };
-
-            //encrypts the lower part of `block`
-            let encrypt_lower = |block_word: &mut W, key_word: W| {
-                // high bits of key are zero after bit shifting, so we can simply xor the whole thing
-                dbg!(bit_offset);
-                let key = key_word >> bit_offset;
-                *block_word ^= key;
-            };
-            if buf.len() == 0 || key.len() == 0 {
-                return;
-            }
-            //encrypt upper part from first key word
-
-            let buf_len = buf.len();
-            let key_word = key[word_offset];
-            let mut block_word = &mut buf[0];
-            encrypt_upper(block_word, key_word);
+    /// Xors `buf` with `L` whole key words starting at a word offset selected by `index`
+    fn xor_words<const L: usize, W: crate::Word>(&self, index: u64, buf: &mut [W; L]) {
+        let key = self.key.borrow();
+        let offset = key.subkey_offset::<W, L>(index);
+        self.observer.record(offset);
+        let key = key.subkey_at::<W, L>(offset);
+
+        // Perform Xor encryption
+        for (word, key_word) in buf.iter_mut().zip(key) {
+            *word ^= W::from_le(*key_word);
+        }
+    }
+
+    /// Xors `buf` with `L` key words starting at a bit offset selected by `index`
+    fn xor_bit_offset<const L: usize, W: crate::Word>(&self, index: u64, buf: &mut [W; L]) {
+        let key = self.key.borrow().as_words::<W>();
+
+        // The index of one past the last element we can access
+        let max_element = key.len() - buf.len();
+        let word_bits = size_of::<W>() * 8;
+        let max_bit = max_element * word_bits;
+
+        // Because of the bit offsets, we may need to strattle two extra words, so subtract the
+        // number of bytes in a word when finding offset.
+        // This is `index % max_bit` using the precomputed reciprocal of `max_bit`
+        let reducer = crate::key::SubkeyReducers::<W, KEY_BYTES, L>::BITS;
+        debug_assert_eq!(reducer.divisor(), max_bit as u64);
+        // The result is less than `max_bit`, so it fits in usize
+        let offset = reducer.reduce(index) as usize;
+        let mut word_offset = offset / word_bits;
+        let bit_offset = offset % word_bits;
+        self.observer.record(word_offset);
+        //start encrypting the high bits of block starting with the key bit at `bit_offset`
 
-            // we have to encrypt the first and last word bits seperately to handle bit alignment,
-            // so run for one less iteration
-            for i in 0..buf_len - 1 {
-                let key_word = key[word_offset];
-                word_offset += 1;
-                dbg!(word_offset, i, key_word);
-                encrypt_lower(block_word, key_word);
-                block_word = &mut buf[i + 1];
-                encrypt_upper(block_word, key_word);
-            }
-            let key_word = key[word_offset + 1];
+        //encrypts the upper part of `block`
+        let encrypt_upper = |block_word: &mut W, key_word: W| {
+            // low bits of key are zero after bit shifting, so we can simply xor the whole thing
+            let key = key_word << bit_offset;
+            *block_word ^= key;
+        };
+
+        //encrypts the lower part of `block`
+        let encrypt_lower = |block_word: &mut W, key_word: W| {
+            // high bits of key are zero after bit shifting, so we can simply xor the whole thing
+            let key = key_word >> bit_offset;
+            *block_word ^= key;
+        };
+        if buf.is_empty() || key.is_empty() {
+            return;
+        }
+        //encrypt upper part from first key word
+
+        let buf_len = buf.len();
+        let key_word = W::from_le(key[word_offset]);
+        let mut block_word = &mut buf[0];
+        encrypt_upper(block_word, key_word);
+
+        // we have to encrypt the first and last word bits seperately to handle bit alignment,
+        // so run for one less iteration
+        for i in 0..buf_len - 1 {
+            let key_word = W::from_le(key[word_offset]);
+            word_offset += 1;
             encrypt_lower(block_word, key_word);
+            block_word = &mut buf[i + 1];
+            encrypt_upper(block_word, key_word);
         }
+        let key_word = W::from_le(key[word_offset + 1]);
+        encrypt_lower(block_word, key_word);
     }
 }
 
@@ -184,12 +300,20 @@ impl Index for u32 {
     fn to_usize(self) -> usize {
         self.try_into().unwrap()
     }
+
+    fn to_u64(self) -> u64 {
+        self.into()
+    }
 }
 
 impl Index for u64 {
     fn to_usize(self) -> usize {
         self.try_into().unwrap()
     }
+
+    fn to_u64(self) -> u64 {
+        self
+    }
 }
 
 impl<const N: usize> GenericCipherBlock<N> {
@@ -219,3 +343,15 @@ impl<const N: usize> AsRef<[u8]> for GenericCipherBlock<N> {
         &self.0
     }
 }
+
+impl<const N: usize> AsMut<[u8]> for GenericCipherBlock<N> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl<const N: usize> Default for GenericCipherBlock<N> {
+    fn default() -> Self {
+        Self([0; N])
+    }
+}
@@ -0,0 +1,444 @@
+//! A block holding a [`Tag`] header together with `N` data words, usable with any algorithm
+
+use core::fmt;
+use core::mem::size_of;
+
+use crate::{
+    HeaderPermutation, HeaderWord, Index, Keyring, KeyringError, Packed, Pod, Tag, WireBytes,
+    WireError, Word,
+};
+
+/// A cipher that encrypts or decrypts blocks of `N` words of type `W` in place, selected by an
+/// index. Implemented by each algorithm so that [`GenericIndexedBlock`] works with any of them
+pub trait WordCipher<W: Word, const N: usize> {
+    /// The index type used to select the keystream
+    type Index: Index;
+
+    /// Encrypts or decrypts `words` using `index`.
+    /// Because Xor is used, the encryption and decryption operation is the same
+    fn cipher_words(&self, index: Self::Index, words: &mut [W; N]);
+}
+
+/// High level index block for storing a tag of type `T` and `N` words of encrypted data of type
+/// `W` together.
+///
+/// Use the aliases [`alg1::IndexedBlock`](crate::alg1::IndexedBlock),
+/// [`alg2::IndexedBlock`](crate::alg2::IndexedBlock) and
+/// [`alg3::IndexedBlock`](crate::alg3::IndexedBlock) for the layouts used by each algorithm.
+///
+/// `A` is an alignment marker such as [`Align8`], for layouts that must be aligned more strictly
+/// than their tag and words. It takes up no space
+#[repr(C)]
+pub struct GenericIndexedBlock<T, W, const N: usize, A = NaturalAlign> {
+    align: [A; 0],
+    tag: T,
+    data: [W; N],
+}
+
+/// Alignment marker for a [`GenericIndexedBlock`] aligned only as much as its tag and words
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NaturalAlign;
+
+/// Alignment marker for a [`GenericIndexedBlock`] aligned to 8 bytes on every target, including
+/// 32 bit targets where `u64` only needs 4
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(align(8))]
+pub struct Align8;
+
+impl<T: Tag + Default, W: Word, const N: usize, A> GenericIndexedBlock<T, W, N, A> {
+    pub fn new() -> Self {
+        Self {
+            align: [],
+            tag: T::default(),
+            data: [W::from(0); N],
+        }
+    }
+}
+
+impl<T: Tag, W: Word, const N: usize, A> GenericIndexedBlock<T, W, N, A> {
+    pub fn data(&self) -> &[W; N] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [W; N] {
+        &mut self.data
+    }
+
+    /// Returns the index of this block, without the tag bits
+    pub fn index(&self) -> T::IndexTy {
+        self.tag.get_index()
+    }
+
+    pub fn tag(&mut self) -> &mut T {
+        &mut self.tag
+    }
+
+    /// Encrypts or decrypts this block with `cipher`, using the index stored in the tag
+    pub fn do_cipher<C: WordCipher<W, N, Index = T::IndexTy>>(&mut self, cipher: &C) {
+        let index = self.tag.get_index();
+        cipher.cipher_words(index, &mut self.data);
+    }
+
+    /// Encrypts or decrypts this block using the cipher in `keyring` whose key ID is stored in the
+    /// tag bits of this block.
+    ///
+    /// Senders select the key with [`Tag::set_tag`] before calling this. Only key IDs that fit in
+    /// [`Tag::tag_bits_count`] bits can be addressed, so [`Tag31_1`](crate::Tag31_1) can only alternate between
+    /// key IDs 0 and 1 during rotation, while [`Tag56_8`](crate::Tag56_8) can address key IDs 0 through 255.
+    /// [`Keyring::insert`] rejects wider key IDs.
+    ///
+    /// Returns Err and leaves the block unchanged if `keyring` holds no key with that ID
+    pub fn do_cipher_keyring<C, const CAP: usize>(
+        &mut self,
+        keyring: &Keyring<T, C, CAP>,
+    ) -> Result<(), KeyringError>
+    where
+        C: WordCipher<W, N, Index = T::IndexTy>,
+    {
+        let id = self.tag.get_tag();
+        let cipher = keyring.get(id)?;
+        self.do_cipher(cipher);
+        Ok(())
+    }
+}
+
+impl<T: Pod, W: Pod, const N: usize, A> GenericIndexedBlock<T, W, N, A> {
+    /// Returns this entire message as a byte slice, sutiable for transmitting
+    pub fn as_bytes(&self) -> &[u8] {
+        crate::pod::bytes_of(self)
+    }
+
+    /// Returns this entire message as a byte slice, sutiable for reciving
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        crate::pod::bytes_of_mut(self)
+    }
+}
+
+/// SAFETY: `Self` is `repr(C)` with fields that are `Pod` besides the zero sized alignment field,
+/// and `NO_PADDING` rejects layouts with padding between or after the fields
+unsafe impl<T: Pod, W: Pod, const N: usize, A> Pod for GenericIndexedBlock<T, W, N, A> {
+    const NO_PADDING: () = {
+        #[allow(clippy::let_unit_value)]
+        let ((), ()) = (T::NO_PADDING, W::NO_PADDING);
+        assert!(
+            size_of::<T>() + N * size_of::<W>() == size_of::<Self>(),
+            "GenericIndexedBlock layouts must not contain padding"
+        );
+    };
+}
+
+impl<T: Tag + Default, W: Word, const N: usize, A> Default for GenericIndexedBlock<T, W, N, A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`GenericIndexedBlock`] together with a CRC-32 of its wire format, so that blocks corrupted
+/// by a noisy channel are rejected before they are decrypted.
+///
+/// The checksum covers the header and ciphertext as sent, so create this after
+/// [`do_cipher`](GenericIndexedBlock::do_cipher) and any
+/// [`encode_header`](GenericIndexedBlock::encode_header). On the wire the checksum follows the
+/// block in little endian byte order.
+///
+/// The CRC is not a message authentication code: it detects accidental corruption, but anyone who
+/// changes a block can also recompute its checksum
+pub struct ChecksummedBlock<T, W, const N: usize, A = NaturalAlign> {
+    block: GenericIndexedBlock<T, W, N, A>,
+    checksum: u32,
+}
+
+impl<T, W, const N: usize, A> ChecksummedBlock<T, W, N, A> {
+    /// Returns the checked block
+    pub fn block(&self) -> &GenericIndexedBlock<T, W, N, A> {
+        &self.block
+    }
+
+    /// Returns the checked block, ready to be decrypted
+    pub fn into_block(self) -> GenericIndexedBlock<T, W, N, A> {
+        self.block
+    }
+
+    /// Returns the CRC-32 of the wire format of the block
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+}
+
+impl<T: Tag, W: Word, const N: usize, A> ChecksummedBlock<T, W, N, A> {
+    /// The size of a checksummed block on the wire, in bytes
+    pub const WIRE_SIZE: usize = GenericIndexedBlock::<T, W, N, A>::WIRE_SIZE + 4;
+
+    /// Computes the checksum of `block`, which should already be encrypted
+    pub fn new(block: GenericIndexedBlock<T, W, N, A>) -> Self {
+        let checksum = crate::crc::crc32(&block.to_wire_bytes());
+        Self { block, checksum }
+    }
+
+    /// Serializes the block in its wire format followed by the checksum
+    pub fn to_wire_bytes(&self) -> ChecksummedWireBytes<T, W, N> {
+        WireBytes(Packed(self.block.to_wire_bytes().0, self.checksum.to_le()))
+    }
+}
+
+impl<T: Tag + Default, W: Word, const N: usize, A> ChecksummedBlock<T, W, N, A> {
+    /// Parses and checks a block written by [`to_wire_bytes`](Self::to_wire_bytes)
+    ///
+    /// Returns Err if `bytes` has the wrong length, or if the checksum does not match because the
+    /// block was corrupted
+    pub fn from_wire_bytes(bytes: &[u8]) -> Result<Self, ChecksumError> {
+        crate::wire::check_length(bytes, Self::WIRE_SIZE)?;
+        let (block, checksum) = bytes.split_at(Self::WIRE_SIZE - 4);
+        let expected = crate::wire::read_le(checksum);
+        let actual = crate::crc::crc32(block);
+        if actual != expected {
+            return Err(ChecksumError::Corrupted { expected, actual });
+        }
+        let block = GenericIndexedBlock::from_wire_bytes(block)?;
+        Ok(Self {
+            block,
+            checksum: actual,
+        })
+    }
+}
+
+/// Errors returned when parsing a [`ChecksummedBlock`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumError {
+    /// The frame did not have the expected length
+    Wire(WireError),
+    /// The checksum did not match the block, so it was corrupted in transit. This only detects
+    /// accidental corruption, and is never the result of authenticating a block
+    Corrupted { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumError::Wire(e) => write!(f, "{}", e),
+            ChecksumError::Corrupted { expected, actual } => write!(
+                f,
+                "block corrupted: checksum {:#010x} does not match {:#010x}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ChecksumError {}
+
+impl From<WireError> for ChecksumError {
+    fn from(e: WireError) -> Self {
+        ChecksumError::Wire(e)
+    }
+}
+
+/// The wire format of a [`GenericIndexedBlock`]: the raw tag followed by the data words
+pub type BlockWireBytes<T, W, const N: usize> = WireBytes<Packed<<T as Tag>::Raw, [W; N]>>;
+
+/// The wire format of a [`ChecksummedBlock`]: the block followed by its CRC-32
+pub type ChecksummedWireBytes<T, W, const N: usize> =
+    WireBytes<Packed<Packed<<T as Tag>::Raw, [W; N]>, u32>>;
+
+impl<T: Tag, W: Word, const N: usize, A> GenericIndexedBlock<T, W, N, A> {
+    /// The number of data bytes in this block
+    pub const DATA_SIZE: usize = N * size_of::<W>();
+
+    /// The size of this block on the wire, in bytes
+    pub const WIRE_SIZE: usize = size_of::<T::Raw>() + Self::DATA_SIZE;
+
+    /// Returns the data words as bytes, each word in little endian byte order like on the wire
+    pub fn data_bytes(&self) -> WireBytes<[W; N]> {
+        WireBytes(self.data.map(W::to_le))
+    }
+
+    /// Sets the data words from bytes, each word in little endian byte order like on the wire
+    ///
+    /// Returns Err and leaves the block unchanged if `bytes` is not exactly
+    /// [`DATA_SIZE`](Self::DATA_SIZE) bytes long
+    pub fn set_data_bytes(&mut self, bytes: &[u8]) -> Result<(), WireError> {
+        crate::wire::check_length(bytes, Self::DATA_SIZE)?;
+        for (word, chunk) in self.data.iter_mut().zip(bytes.chunks_exact(size_of::<W>())) {
+            *word = crate::wire::read_le(chunk);
+        }
+        Ok(())
+    }
+
+    /// Replaces the header (index and tag bits) of this block with its keyed permutation, so the
+    /// index is not sent in the clear. Call this after [`do_cipher`](Self::do_cipher) and before
+    /// sending.
+    ///
+    /// The index and tag are meaningless until [`decode_header`](Self::decode_header) is called
+    /// with the same permutation
+    pub fn encode_header(&mut self, permutation: &HeaderPermutation)
+    where
+        T::Raw: HeaderWord,
+    {
+        self.tag = T::from_raw(permutation.encode(self.tag.to_raw()));
+    }
+
+    /// Recovers the header of a block received with an encoded header. Call this before
+    /// [`do_cipher`](Self::do_cipher)
+    pub fn decode_header(&mut self, permutation: &HeaderPermutation)
+    where
+        T::Raw: HeaderWord,
+    {
+        self.tag = T::from_raw(permutation.decode(self.tag.to_raw()));
+    }
+
+    /// Serializes this block into its wire format: the tag followed by each data word, all in
+    /// little endian byte order. Unlike [`as_bytes`](Self::as_bytes), the result is the same on
+    /// every platform
+    pub fn to_wire_bytes(&self) -> BlockWireBytes<T, W, N> {
+        WireBytes(Packed(self.tag.to_raw().to_le(), self.data_bytes().0))
+    }
+}
+
+impl<T: Tag + Default, W: Word, const N: usize, A> GenericIndexedBlock<T, W, N, A> {
+    /// Parses a block from its wire format, written by [`to_wire_bytes`](Self::to_wire_bytes)
+    ///
+    /// Returns Err if `bytes` is not exactly [`WIRE_SIZE`](Self::WIRE_SIZE) bytes long
+    pub fn from_wire_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        crate::wire::check_length(bytes, Self::WIRE_SIZE)?;
+        let (tag, data) = bytes.split_at(size_of::<T::Raw>());
+        let mut block = Self::new();
+        block.set_data_bytes(data)?;
+        block.tag = T::from_raw(crate::wire::read_le(tag));
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alg1::Algorithm1;
+    use crate::{Key, Tag28_4};
+    use core::mem::{align_of, size_of};
+
+    #[test]
+    fn alias_layout() {
+        // The aliases must keep the layout of the blocks they replaced
+        assert_eq!(size_of::<crate::alg1::IndexedBlock>(), 32);
+        assert_eq!(align_of::<crate::alg1::IndexedBlock>(), 4);
+        assert_eq!(size_of::<crate::alg2::IndexedBlock>(), 256);
+        assert_eq!(align_of::<crate::alg2::IndexedBlock>(), 8);
+
+        let mut block = crate::alg1::IndexedBlock::new();
+        block.tag().set_index(0x0102_0304);
+        block.data_mut()[0] = 0x0506_0708;
+        #[cfg(target_endian = "little")]
+        assert_eq!(block.as_bytes()[..8], [4, 3, 2, 1, 8, 7, 6, 5]);
+    }
+
+    #[test]
+    fn other_tags() {
+        // Any tag with the index type of the cipher works, here giving 16 key IDs
+        let key = Key::new([0x5Au8; 256]);
+        let cipher = Algorithm1::new(&key, 17);
+
+        let mut block: GenericIndexedBlock<Tag28_4, u32, 7> = GenericIndexedBlock::new();
+        block.data_mut().copy_from_slice(&[1, 2, 3, 4, 5, 6, 7]);
+        block.tag().set_index(300);
+        block.tag().set_tag(15);
+        block.do_cipher(&cipher);
+
+        // The tag bits do not affect the ciphertext
+        let mut expected = crate::alg1::IndexedBlock::new();
+        expected.data_mut().copy_from_slice(&[1, 2, 3, 4, 5, 6, 7]);
+        expected.tag().set_index(300);
+        expected.do_cipher(&cipher);
+        assert_eq!(block.data(), expected.data());
+
+        block.do_cipher(&cipher);
+        assert_eq!(block.data(), &[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(block.index(), 300);
+        assert_eq!(block.tag().get_tag(), 15);
+    }
+
+    #[test]
+    fn checksummed() {
+        use crate::alg1::ChecksummedBlock;
+
+        let key = Key::new([0x33u8; 128]);
+        let cipher = Algorithm1::new(&key, 5);
+        let mut block = crate::alg1::IndexedBlock::new();
+        block.data_mut().copy_from_slice(&[7; 7]);
+        block.tag().set_index(1000);
+        block.do_cipher(&cipher);
+
+        let checked = ChecksummedBlock::new(block);
+        let wire = checked.to_wire_bytes();
+        assert_eq!(wire.len(), 36);
+        assert_eq!(wire[..32], checked.block().to_wire_bytes());
+        assert_eq!(wire[32..], crate::crc::crc32(&wire[..32]).to_le_bytes());
+
+        // Every single bit flip in the header, ciphertext or checksum is rejected
+        for bit in 0..wire.len() * 8 {
+            let mut noisy = wire;
+            noisy[bit / 8] ^= 1 << (bit % 8);
+            assert!(matches!(
+                ChecksummedBlock::from_wire_bytes(&noisy),
+                Err(ChecksumError::Corrupted { .. })
+            ));
+        }
+        assert_eq!(
+            ChecksummedBlock::from_wire_bytes(&wire[..35]).err(),
+            Some(ChecksumError::Wire(WireError::WrongLength {
+                expected: 36,
+                actual: 35
+            }))
+        );
+
+        let received = ChecksummedBlock::from_wire_bytes(&wire).unwrap();
+        assert_eq!(received.checksum(), checked.checksum());
+        let mut received = received.into_block();
+        received.do_cipher(&cipher);
+        assert_eq!(received.data(), &[7; 7]);
+        assert_eq!(crate::alg2::ChecksummedBlock::WIRE_SIZE, 260);
+
+        // Any layout can be checksummed
+        let mut block: GenericIndexedBlock<Tag28_4, u32, 7> = GenericIndexedBlock::new();
+        block.tag().set_tag(9);
+        let wire = super::ChecksummedBlock::new(block).to_wire_bytes();
+        assert_eq!(wire.len(), 36);
+        let received = super::ChecksummedBlock::<Tag28_4, u32, 7>::from_wire_bytes(&wire).unwrap();
+        assert_eq!(received.into_block().tag().get_tag(), 9);
+    }
+
+    #[test]
+    fn wire_any_layout() {
+        // Layouts without an algorithm alias can also be sent and have their header permuted
+        type Block = GenericIndexedBlock<Tag28_4, u16, 5>;
+        assert_eq!(Block::DATA_SIZE, 10);
+        assert_eq!(Block::WIRE_SIZE, 14);
+
+        let mut block = Block::new();
+        block.tag().set_index(0x0102_0304);
+        block.tag().set_tag(0xA);
+        block
+            .data_mut()
+            .copy_from_slice(&[0x0506, 0x0708, 0, 0, 0xFFEE]);
+        let wire = block.to_wire_bytes();
+        assert_eq!(wire, [4, 3, 2, 0xA1, 6, 5, 8, 7, 0, 0, 0, 0, 0xEE, 0xFF]);
+
+        let permutation = HeaderPermutation::new(99);
+        let mut sent = Block::from_wire_bytes(&wire).unwrap();
+        sent.encode_header(&permutation);
+        assert_ne!(sent.to_wire_bytes()[..4], wire[..4]);
+        let mut received = Block::from_wire_bytes(&sent.to_wire_bytes()).unwrap();
+        received.decode_header(&permutation);
+        assert_eq!(received.to_wire_bytes(), wire);
+        assert_eq!(received.index(), 0x0102_0304);
+        assert_eq!(received.tag().get_tag(), 0xA);
+
+        assert_eq!(
+            block.set_data_bytes(&[0; 9]),
+            Err(WireError::WrongLength {
+                expected: 10,
+                actual: 9
+            })
+        );
+        assert_eq!(block.data(), &[0x0506, 0x0708, 0, 0, 0xFFEE]);
+    }
+}
@@ -0,0 +1,241 @@
+//! Algorithm for encrypting 28 byte blocks with the ChaCha20 keystream from
+//! [RFC 8439](https://www.rfc-editor.org/rfc/rfc8439), as a conservative alternative to the
+//! key slicing algorithms.
+//!
+//! The ChaCha20 key is the first 32 bytes of the [`Key`], and the 32 bit index is the first word
+//! of the nonce, with the block counter starting at zero. Each block uses the first 28 bytes of
+//! one 64 byte ChaCha20 block, so as with the other algorithms an index must never be reused with
+//! the same key. Blocks have the same layout as [`alg1`](crate::alg1)
+
+use crate::{AlgorithmId, BlockCipher, GenericCipherBlock, Key, WordCipher};
+
+const BLOCK_SIZE: usize = 28;
+const ELEMENT_COUNT: usize = 7;
+
+/// The number of key bytes used by ChaCha20
+pub const KEY_SIZE: usize = 32;
+
+/// The first row of the ChaCha20 state, "expand 32-byte k" in little endian words
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646E, 0x7962_2D32, 0x6B20_6574];
+
+pub type CipherBlock = GenericCipherBlock<BLOCK_SIZE>;
+
+/// Encrypts blocks by Xoring them with the ChaCha20 keystream
+#[derive(Clone)]
+pub struct ChaCha20 {
+    key: [u32; 8],
+}
+
+impl ChaCha20 {
+    /// Creates a cipher from the first [`KEY_SIZE`] bytes of `key`.
+    ///
+    /// # Panics
+    /// If `key` is shorter than [`KEY_SIZE`] bytes
+    pub const fn new<const KEY_BYTES: usize>(key: &Key<KEY_BYTES>) -> Self {
+        assert!(
+            KEY_BYTES >= KEY_SIZE,
+            "ChaCha20 needs a key of at least 32 bytes"
+        );
+        let bytes = key.as_bytes();
+        let mut words = [0u32; 8];
+        let mut i = 0;
+        while i < words.len() {
+            let b = 4 * i;
+            words[i] = u32::from_le_bytes([bytes[b], bytes[b + 1], bytes[b + 2], bytes[b + 3]]);
+            i += 1;
+        }
+        Self { key: words }
+    }
+
+    /// Encrypts or decrypts a single block using `index`.
+    /// Because Xor is used, the encryption and decryption operation is the same
+    pub fn cipher_block(&self, index: u32, block: &mut CipherBlock) {
+        let keystream = self.keystream(index);
+        for (byte, key) in block.0.iter_mut().zip(keystream) {
+            *byte ^= key;
+        }
+    }
+
+    fn keystream(&self, index: u32) -> [u8; 64] {
+        block(&self.key, 0, &[index, 0, 0])
+    }
+}
+
+/// High level index block for storing index and encrypted data togther, optimized for 32 byte
+/// messages
+pub type IndexedBlock = crate::alg1::IndexedBlock;
+
+/// An [`IndexedBlock`] followed by a CRC-32, for detecting corruption before decrypting
+pub type ChecksummedBlock = crate::alg1::ChecksummedBlock;
+
+impl WordCipher<u32, ELEMENT_COUNT> for ChaCha20 {
+    type Index = u32;
+
+    /// Xors `words` with the keystream in little endian words, so that this matches
+    /// [`ChaCha20::cipher_block`] on the wire bytes of `words` on every platform
+    fn cipher_words(&self, index: u32, words: &mut [u32; ELEMENT_COUNT]) {
+        let keystream = self.keystream(index);
+        for (word, key) in words.iter_mut().zip(keystream.chunks_exact(4)) {
+            *word ^= u32::from_le_bytes(key.try_into().unwrap());
+        }
+    }
+}
+
+impl BlockCipher for ChaCha20 {
+    const ID: AlgorithmId = AlgorithmId::ChaCha20;
+    const BLOCK_SIZE: usize = BLOCK_SIZE;
+    const INDEXED_BLOCK_SIZE: usize = IndexedBlock::WIRE_SIZE;
+
+    type Index = u32;
+    type Block = CipherBlock;
+    type IndexedBlock = IndexedBlock;
+
+    fn cipher_block(&self, index: u32, block: &mut CipherBlock) {
+        ChaCha20::cipher_block(self, index, block)
+    }
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// The ChaCha20 block function. Returns 64 bytes of keystream for `counter` and `nonce`
+fn block(key: &[u32; 8], counter: u32, nonce: &[u32; 3]) -> [u8; 64] {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter;
+    input[13..].copy_from_slice(nonce);
+
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; 64];
+    for ((bytes, word), input) in out.chunks_exact_mut(4).zip(state).zip(input) {
+        bytes.copy_from_slice(&word.wrapping_add(input).to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tag;
+    use rand::{RngCore, SeedableRng};
+
+    /// The key 00 01 02 .. 1f used by the RFC 8439 test vectors
+    fn rfc_key() -> [u32; 8] {
+        let mut bytes = [0u8; 64];
+        for (i, byte) in bytes.iter_mut().enumerate().take(KEY_SIZE) {
+            *byte = i as u8;
+        }
+        ChaCha20::new(&Key::new(bytes)).key
+    }
+
+    #[test]
+    fn rfc8439_quarter_round() {
+        // Section 2.1.1
+        let mut state = [0u32; 16];
+        state[..4].copy_from_slice(&[0x1111_1111, 0x0102_0304, 0x9B8D_6F43, 0x0123_4567]);
+        quarter_round(&mut state, 0, 1, 2, 3);
+        assert_eq!(
+            state[..4],
+            [0xEA2A_92F4, 0xCB1C_F8CE, 0x4581_472E, 0x5881_C4BB]
+        );
+    }
+
+    #[test]
+    fn rfc8439_block() {
+        // Section 2.3.2
+        let nonce = [0x0900_0000, 0x4A00_0000, 0];
+        let expected = [
+            0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20,
+            0x71, 0xc4, 0xc7, 0xd1, 0xf4, 0xc7, 0x33, 0xc0, 0x68, 0x03, 0x04, 0x22, 0xaa, 0x9a,
+            0xc3, 0xd4, 0x6c, 0x4e, 0xd2, 0x82, 0x64, 0x46, 0x07, 0x9f, 0xaa, 0x09, 0x14, 0xc2,
+            0xd7, 0x05, 0xd9, 0x8b, 0x02, 0xa2, 0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9,
+            0xcb, 0xd0, 0x83, 0xe8, 0xa2, 0x50, 0x3c, 0x4e,
+        ];
+        assert_eq!(block(&rfc_key(), 1, &nonce), expected);
+    }
+
+    #[test]
+    fn rfc8439_encryption() {
+        // Section 2.4.2
+        let nonce = [0, 0x4A00_0000, 0];
+        let mut text = *b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+            tip for the future, sunscreen would be it.";
+        let expected = [
+            0x6e, 0x2e, 0x35, 0x9a, 0x25, 0x68, 0xf9, 0x80, 0x41, 0xba, 0x07, 0x28, 0xdd, 0x0d,
+            0x69, 0x81, 0xe9, 0x7e, 0x7a, 0xec, 0x1d, 0x43, 0x60, 0xc2, 0x0a, 0x27, 0xaf, 0xcc,
+            0xfd, 0x9f, 0xae, 0x0b, 0xf9, 0x1b, 0x65, 0xc5, 0x52, 0x47, 0x33, 0xab, 0x8f, 0x59,
+            0x3d, 0xab, 0xcd, 0x62, 0xb3, 0x57, 0x16, 0x39, 0xd6, 0x24, 0xe6, 0x51, 0x52, 0xab,
+            0x8f, 0x53, 0x0c, 0x35, 0x9f, 0x08, 0x61, 0xd8, 0x07, 0xca, 0x0d, 0xbf, 0x50, 0x0d,
+            0x6a, 0x61, 0x56, 0xa3, 0x8e, 0x08, 0x8a, 0x22, 0xb6, 0x5e, 0x52, 0xbc, 0x51, 0x4d,
+            0x16, 0xcc, 0xf8, 0x06, 0x81, 0x8c, 0xe9, 0x1a, 0xb7, 0x79, 0x37, 0x36, 0x5a, 0xf9,
+            0x0b, 0xbf, 0x74, 0xa3, 0x5b, 0xe6, 0xb4, 0x0b, 0x8e, 0xed, 0xf2, 0x78, 0x5e, 0x42,
+            0x87, 0x4d,
+        ];
+        let key = rfc_key();
+        for (counter, chunk) in text.chunks_mut(64).enumerate() {
+            let keystream = block(&key, 1 + counter as u32, &nonce);
+            for (byte, key) in chunk.iter_mut().zip(keystream) {
+                *byte ^= key;
+            }
+        }
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut key_bytes = [0u8; 64];
+        rng.fill_bytes(&mut key_bytes);
+        let cipher = ChaCha20::new(&Key::new(key_bytes));
+
+        for index in 0..100u32 {
+            let mut block = IndexedBlock::new();
+            block.tag().set_index(index);
+            for word in block.data_mut() {
+                *word = rng.next_u32();
+            }
+            let original = *block.data();
+
+            // Must match encrypting the bytes of the block directly
+            let mut expected = CipherBlock::new([0; BLOCK_SIZE]);
+            for (bytes, word) in expected.0.chunks_exact_mut(4).zip(block.data()) {
+                bytes.copy_from_slice(&word.to_le_bytes());
+            }
+            cipher.cipher_block(index, &mut expected);
+
+            block.do_cipher(&cipher);
+            assert_ne!(block.data(), &original);
+            for (bytes, word) in expected.0.chunks_exact(4).zip(block.data()) {
+                assert_eq!(bytes, word.to_le_bytes());
+            }
+            block.do_cipher(&cipher);
+            assert_eq!(block.data(), &original);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn short_key() {
+        ChaCha20::new(&Key::new([0u8; 16]));
+    }
+}
@@ -0,0 +1,301 @@
+//! Traits implemented by every algorithm, for writing code that works with any of them.
+//!
+//! [`BlockCipher`] exposes the block, index and indexed block types of an algorithm, so generic
+//! code is checked at compile time. [`DynBlockCipher`] is implemented for every `BlockCipher` and
+//! works on byte slices and `u64` indices instead, so it can be used as a trait object when the
+//! algorithm is chosen at runtime:
+//!
+//! ```
+//! use encryption::{DynBlockCipher, Key, alg1::Algorithm1, alg2::Algorithm2};
+//!
+//! let key = Key::new([7u8; 512]);
+//! let alg1 = Algorithm1::new(&key, 0x1234);
+//! let alg2 = Algorithm2::new(&key, 0x1234);
+//! // For example from a configuration file
+//! let configured = "alg2";
+//! let cipher: &dyn DynBlockCipher = if configured == alg1.name() { &alg1 } else { &alg2 };
+//!
+//! let mut block = vec![0u8; cipher.block_size()];
+//! cipher.cipher_bytes(42, &mut block).unwrap();
+//! cipher.cipher_bytes(42, &mut block).unwrap();
+//! assert_eq!(block, [0; 248]);
+//! ```
+
+use core::fmt;
+
+use crate::{AlgorithmId, Index, WordMode};
+
+/// A cipher that encrypts or decrypts fixed size blocks in place, selected by an index
+pub trait BlockCipher {
+    /// Identifies the algorithm in message headers
+    const ID: AlgorithmId;
+
+    /// The number of bytes in a [`BlockCipher::Block`]
+    const BLOCK_SIZE: usize;
+
+    /// The number of bytes in a [`BlockCipher::IndexedBlock`] on the wire, including its tag
+    const INDEXED_BLOCK_SIZE: usize;
+
+    /// The index type used to select the keystream
+    type Index: Index + TryFrom<u64>;
+
+    /// A block of [`BlockCipher::BLOCK_SIZE`] bytes
+    type Block: Default + AsRef<[u8]> + AsMut<[u8]>;
+
+    /// The block type holding a tag and data words used with this algorithm
+    type IndexedBlock;
+
+    /// Returns how the keystream is read from the key, or None if the algorithm does not slice
+    /// its keystream from the key
+    fn word_mode(&self) -> Option<WordMode> {
+        None
+    }
+
+    /// Encrypts or decrypts `block` using `index`.
+    /// Because Xor is used, the encryption and decryption operation is the same
+    fn cipher_block(&self, index: Self::Index, block: &mut Self::Block);
+}
+
+/// An object safe version of [`BlockCipher`], implemented for every `BlockCipher`
+pub trait DynBlockCipher {
+    /// Returns [`BlockCipher::ID`]
+    fn algorithm(&self) -> AlgorithmId;
+
+    /// Returns the name of [`DynBlockCipher::algorithm`], for configuration and logging
+    fn name(&self) -> &'static str {
+        self.algorithm().name()
+    }
+
+    /// Returns [`BlockCipher::BLOCK_SIZE`]
+    fn block_size(&self) -> usize;
+
+    /// Returns [`BlockCipher::INDEXED_BLOCK_SIZE`]
+    fn indexed_block_size(&self) -> usize;
+
+    /// Returns [`BlockCipher::word_mode`]
+    fn word_mode(&self) -> Option<WordMode>;
+
+    /// Returns the number of bits in the index type, so indices must be below
+    /// `2^index_bits()`
+    fn index_bits(&self) -> u32;
+
+    /// Encrypts or decrypts `block` using `index`.
+    ///
+    /// Returns Err and leaves `block` unchanged if it is not [`DynBlockCipher::block_size`] bytes
+    /// long, or if `index` does not fit in the index type of the algorithm
+    fn cipher_bytes(&self, index: u64, block: &mut [u8]) -> Result<(), CipherError>;
+}
+
+impl<C: BlockCipher> DynBlockCipher for C {
+    fn algorithm(&self) -> AlgorithmId {
+        C::ID
+    }
+
+    fn block_size(&self) -> usize {
+        C::BLOCK_SIZE
+    }
+
+    fn indexed_block_size(&self) -> usize {
+        C::INDEXED_BLOCK_SIZE
+    }
+
+    fn word_mode(&self) -> Option<WordMode> {
+        BlockCipher::word_mode(self)
+    }
+
+    fn index_bits(&self) -> u32 {
+        (core::mem::size_of::<C::Index>() * 8) as u32
+    }
+
+    fn cipher_bytes(&self, index: u64, block: &mut [u8]) -> Result<(), CipherError> {
+        if block.len() != C::BLOCK_SIZE {
+            return Err(CipherError::WrongBlockSize {
+                expected: C::BLOCK_SIZE,
+                actual: block.len(),
+            });
+        }
+        let index =
+            C::Index::try_from(index).map_err(|_| CipherError::IndexOutOfRange { index })?;
+
+        // Copy into an aligned block, since `block` may have any alignment
+        let mut aligned = C::Block::default();
+        aligned.as_mut().copy_from_slice(block);
+        self.cipher_block(index, &mut aligned);
+        block.copy_from_slice(aligned.as_ref());
+        Ok(())
+    }
+}
+
+/// Errors returned by [`DynBlockCipher::cipher_bytes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherError {
+    /// The block was not the block size of the algorithm
+    WrongBlockSize { expected: usize, actual: usize },
+    /// The index does not fit in the index type of the algorithm
+    IndexOutOfRange { index: u64 },
+}
+
+impl fmt::Display for CipherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CipherError::WrongBlockSize { expected, actual } => write!(
+                f,
+                "wrong block size: expected {} bytes, got {}",
+                expected, actual
+            ),
+            CipherError::IndexOutOfRange { index } => {
+                write!(f, "index {} is too large for this algorithm", index)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CipherError {}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        alg1, alg2, alg3, chacha, GenericIndexedBlock, Key, Keyring, KeyringError, Tag, Word,
+        WordCipher,
+    };
+    use rand::{RngCore, SeedableRng};
+    #[cfg(feature = "alloc")]
+    use std::vec::Vec;
+
+    /// Encrypts a block through both traits and checks they agree and round trip
+    pub(crate) fn check<C: BlockCipher>(cipher: &C, index: C::Index, dyn_index: u64) {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(dyn_index);
+        let mut block = C::Block::default();
+        rng.fill_bytes(block.as_mut());
+        let mut bytes = block.as_ref().to_vec();
+        assert_eq!(bytes.len(), C::BLOCK_SIZE);
+
+        cipher.cipher_block(index, &mut block);
+        let dyn_cipher: &dyn DynBlockCipher = cipher;
+        dyn_cipher.cipher_bytes(dyn_index, &mut bytes).unwrap();
+        assert_eq!(block.as_ref(), &bytes[..]);
+
+        dyn_cipher.cipher_bytes(dyn_index, &mut bytes).unwrap();
+        cipher.cipher_block(index, &mut block);
+        assert_eq!(block.as_ref(), &bytes[..]);
+    }
+
+    /// Encrypts `block` with the cipher in `keyring` selected by its tag bits, and checks the
+    /// data matches encrypting its wire bytes directly with that cipher, so the tag bits only select
+    /// the key, and that the block round trips. Then checks an unknown key ID leaves it unchanged
+    pub(crate) fn keyring_round_trip<T, W, C, A, const N: usize>(
+        keyring: &Keyring<T, C, 2>,
+        mut block: GenericIndexedBlock<T, W, N, A>,
+    ) where
+        T: Tag<IndexTy = u64> + Default,
+        W: Word + PartialEq,
+        C: BlockCipher<Index = u64> + WordCipher<W, N, Index = u64>,
+    {
+        let index = block.index();
+        let id = block.tag().get_tag();
+        let original = *block.data();
+
+        let mut expected = C::Block::default();
+        expected.as_mut().copy_from_slice(&block.data_bytes());
+        keyring.get(id).unwrap().cipher_block(index, &mut expected);
+
+        block.do_cipher_keyring(keyring).unwrap();
+        assert_eq!(block.index(), index);
+        assert_eq!(block.tag().get_tag(), id);
+        assert_eq!(&block.data_bytes()[..], expected.as_ref());
+
+        block.do_cipher_keyring(keyring).unwrap();
+        assert_eq!(block.data(), &original);
+
+        let unknown = (0..).find(|id| keyring.get(*id).is_err()).unwrap();
+        block.tag().set_tag(unknown);
+        assert_eq!(
+            block.do_cipher_keyring(keyring),
+            Err(KeyringError::UnknownKeyId(unknown))
+        );
+        assert_eq!(block.data(), &original);
+    }
+
+    /// Encrypts blocks with `shared` on several threads, which only compiles if it is
+    /// `Send + Sync + 'static`, and checks that `borrowed`, holding the same key, decrypts them
+    #[cfg(feature = "alloc")]
+    pub(crate) fn shared_key<C, B>(shared: C, borrowed: &B)
+    where
+        C: BlockCipher + Send + Sync + 'static,
+        C::Block: Send,
+        B: BlockCipher<Index = C::Index, Block = C::Block>,
+    {
+        let index = |t: u64| C::Index::try_from(t << 24 | t).ok().unwrap();
+        let shared = std::sync::Arc::new(shared);
+        let threads: Vec<_> = (0..4u64)
+            .map(|t| {
+                let cipher = shared.clone();
+                std::thread::spawn(move || {
+                    let mut block = C::Block::default();
+                    block.as_mut().fill(t as u8);
+                    cipher.cipher_block(index(t), &mut block);
+                    block
+                })
+            })
+            .collect();
+
+        for (t, thread) in (0..4u64).zip(threads) {
+            let mut block = thread.join().unwrap();
+            assert!(block.as_ref().iter().any(|&b| b != t as u8));
+            borrowed.cipher_block(index(t), &mut block);
+            assert!(block.as_ref().iter().all(|&b| b == t as u8));
+        }
+    }
+
+    #[test]
+    fn all_algorithms() {
+        let key = Key::new([0x42u8; 512]);
+        check(&alg1::Algorithm1::new(&key, 1), 7, 7);
+        check(&alg2::Algorithm2::new(&key, 2), 1 << 40, 1 << 40);
+        check(&alg3::Algorithm3::new(&key, 3), 9, 9);
+        check(&chacha::ChaCha20::new(&key), 11, 11);
+
+        let ciphers: [&dyn DynBlockCipher; 4] = [
+            &alg1::Algorithm1::new(&key, 1),
+            &alg2::Algorithm2::new(&key, 2),
+            &alg3::Algorithm3::new(&key, 3),
+            &chacha::ChaCha20::new(&key),
+        ];
+        let sizes: [(&str, usize, usize); 4] = [
+            ("alg1", 28, 32),
+            ("alg2", 248, 256),
+            ("alg3", 56, 64),
+            ("chacha20", 28, 32),
+        ];
+        for (cipher, (name, block, indexed)) in ciphers.iter().zip(sizes) {
+            assert_eq!(cipher.name(), name);
+            assert_eq!(
+                cipher.index_bits(),
+                if block == 248 || block == 56 { 64 } else { 32 }
+            );
+            assert_eq!(cipher.block_size(), block);
+            assert_eq!(cipher.indexed_block_size(), indexed);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let key = Key::new([0x42u8; 64]);
+        let cipher: &dyn DynBlockCipher = &alg1::Algorithm1::new(&key, 1);
+        let mut block = [1u8; 29];
+        assert_eq!(
+            cipher.cipher_bytes(0, &mut block),
+            Err(CipherError::WrongBlockSize {
+                expected: 28,
+                actual: 29
+            })
+        );
+        assert_eq!(
+            cipher.cipher_bytes(1 << 32, &mut block[..28]),
+            Err(CipherError::IndexOutOfRange { index: 1 << 32 })
+        );
+        assert_eq!(block, [1; 29]);
+    }
+}
@@ -0,0 +1,107 @@
+//! Table driven CRC-32 (IEEE 802.3), for detecting accidental corruption.
+//!
+//! A CRC is not a message authentication code. Anyone who changes a message can recompute its
+//! checksum, so it only protects against noise, not against an attacker
+
+const POLY: u32 = 0xEDB8_8320;
+
+/// `TABLE[b]` is the CRC register after shifting the byte `b` through it
+const TABLE: [u32; 256] = table();
+
+const fn table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// An incremental CRC-32 computation, for data that is not in a single slice
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self { crc: !0 }
+    }
+
+    /// Adds `bytes` to the checksum
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.crc = (self.crc >> 8) ^ TABLE[((self.crc ^ byte as u32) & 0xFF) as usize];
+        }
+    }
+
+    /// Returns the checksum of all bytes added so far
+    pub fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the CRC-32 of `bytes`
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{RngCore, SeedableRng};
+
+    /// The bit at a time definition the table is built from
+    fn bitwise(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in bytes {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (POLY & mask);
+            }
+        }
+        !crc
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn matches_bitwise() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut bytes = [0u8; 300];
+        rng.fill_bytes(&mut bytes);
+        for len in 0..bytes.len() {
+            assert_eq!(crc32(&bytes[..len]), bitwise(&bytes[..len]));
+        }
+
+        let mut crc = Crc32::new();
+        for chunk in bytes.chunks(7) {
+            crc.update(chunk);
+        }
+        assert_eq!(crc.finish(), crc32(&bytes));
+    }
+}
@@ -0,0 +1,139 @@
+//! Exact modular reduction by a divisor that is known ahead of time, using a precomputed
+//! reciprocal instead of a division instruction.
+//!
+//! See Lemire, Kaser and Kurz, "Faster Remainder by Direct Computation" (2019). With a 128 bit
+//! reciprocal the result is exact for every 64 bit numerator and divisor, so ciphertexts are
+//! identical to those produced with the `%` operator.
+//!
+//! # Uniformity
+//! Reduction is exact, so the only bias comes from the divisor not dividing the range of the
+//! numerator. If `n` is uniformly distributed over `2^b` values and the divisor is `d`, every
+//! remainder is produced either `floor(2^b / d)` or `ceil(2^b / d)` times, and exactly `2^b mod d`
+//! remainders are produced the larger number of times. The most used remainder is therefore used at
+//! most `1 + 1 / floor(2^b / d)` times as often as the least used one. For the 32 bit indices of
+//! [`Algorithm1`](crate::alg1::Algorithm1) with [`KEY`](crate::KEY) this is less than
+//! `1 + 4e-6` for word offsets (`d = 13314`) and `1 + 1e-4` for bit offsets (`d = 426016`). For
+//! 64 bit indices it is negligible.
+
+/// A divisor together with its precomputed reciprocal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FastMod {
+    divisor: u64,
+    /// `ceil(2^128 / divisor)`, or 0 when `divisor` is 1
+    reciprocal: u128,
+}
+
+impl FastMod {
+    /// Precomputes the reciprocal of `divisor`
+    ///
+    /// # Panics
+    /// If `divisor` is zero
+    pub const fn new(divisor: u64) -> Self {
+        assert!(divisor != 0, "Cannot reduce modulo zero");
+        let reciprocal = if divisor == 1 {
+            // 2^128 does not fit, but every number is 0 modulo 1, which a zero reciprocal gives
+            0
+        } else {
+            u128::MAX / divisor as u128 + 1
+        };
+        Self {
+            divisor,
+            reciprocal,
+        }
+    }
+
+    /// Returns the divisor
+    pub const fn divisor(&self) -> u64 {
+        self.divisor
+    }
+
+    /// Returns `n % self.divisor()`
+    #[inline]
+    pub const fn reduce(&self, n: u64) -> u64 {
+        let low_bits = self.reciprocal.wrapping_mul(n as u128);
+        // The remainder is the high 64 bits of the 192 bit product `low_bits * divisor`
+        let divisor = self.divisor as u128;
+        let low = (low_bits as u64 as u128) * divisor;
+        let high = (low_bits >> 64) * divisor;
+        ((high + (low >> 64)) >> 64) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{RngCore, SeedableRng};
+    use std::vec;
+
+    #[test]
+    fn matches_remainder() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let divisors = [
+            1,
+            2,
+            3,
+            7,
+            13314,
+            53248,
+            u32::MAX as u64,
+            u32::MAX as u64 + 2,
+            (1 << 63) + 1,
+            u64::MAX - 1,
+            u64::MAX,
+        ];
+        for &divisor in divisors.iter() {
+            let reducer = FastMod::new(divisor);
+            assert_eq!(reducer.divisor(), divisor);
+            let edges = [
+                0,
+                1,
+                divisor - 1,
+                divisor,
+                divisor.wrapping_add(1),
+                u64::MAX,
+            ];
+            for &n in edges.iter() {
+                assert_eq!(reducer.reduce(n), n % divisor, "{} % {}", n, divisor);
+            }
+            for _ in (0..10_000).step_by(crate::test_step(500)) {
+                let n = rng.next_u64();
+                assert_eq!(reducer.reduce(n), n % divisor, "{} % {}", n, divisor);
+                let n = rng.next_u32() as u64;
+                assert_eq!(reducer.reduce(n), n % divisor, "{} % {}", n, divisor);
+            }
+        }
+
+        for divisor in (1..2000u64).step_by(crate::test_step(97)) {
+            let reducer = FastMod::new(divisor);
+            for n in (0..2000).step_by(crate::test_step(97)) {
+                assert_eq!(reducer.reduce(n), n % divisor);
+            }
+        }
+    }
+
+    #[test]
+    fn uniformity_guarantee() {
+        // Every remainder of a uniform input below `range` occurs floor(range / d) or
+        // ceil(range / d) times, with exactly range mod d remainders occurring the larger number
+        // of times
+        let range = (1 << 16) / crate::test_step(64) as u64;
+        for divisor in [3u64, 7, 100, 1000, 4095, 4097, 13314, 65535] {
+            let reducer = FastMod::new(divisor);
+            let mut counts = vec![0u64; divisor as usize];
+            for n in 0..range {
+                counts[reducer.reduce(n) as usize] += 1;
+            }
+            let floor = range / divisor;
+            let larger = counts.iter().filter(|&&c| c == floor + 1).count() as u64;
+            let smaller = counts.iter().filter(|&&c| c == floor).count() as u64;
+            assert_eq!(larger, range % divisor);
+            assert_eq!(larger + smaller, divisor);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_divisor() {
+        let _ = FastMod::new(0);
+    }
+}
@@ -0,0 +1,421 @@
+//! Forward error correction for blocks sent over noisy links.
+//!
+//! [`ReedSolomon`] is a systematic Reed-Solomon code over GF(2^8). `PARITY` check bytes are
+//! appended to the data, and up to `PARITY / 2` corrupted bytes anywhere in the codeword, including
+//! the check bytes themselves, are corrected. More errors than that are detected with high
+//! probability, but may rarely be miscorrected into a different valid codeword.
+//!
+//! A flipped bit in the tag of a block makes the receiver decrypt with the wrong index, so frames
+//! should be corrected before the header is decoded and the block is decrypted:
+//!
+//! ```
+//! use encryption::{Key, Tag, alg1::{Algorithm1, IndexedBlock}, fec};
+//!
+//! let key = Key::new([3u8; 64]);
+//! let cipher = Algorithm1::new(&key, 0xABCD);
+//!
+//! let mut block = IndexedBlock::new();
+//! block.data_mut()[0] = 42;
+//! block.tag().set_index(1234);
+//! block.do_cipher(&cipher);
+//! let mut frame = fec::encode_block(&block);
+//!
+//! // The radio flips bits in the tag and data
+//! frame[0] ^= 0x10;
+//! frame[20] ^= 0xFF;
+//!
+//! let (mut received, corrected) = fec::decode_block(&frame).unwrap();
+//! assert_eq!(corrected, 2);
+//! assert_eq!(received.tag().get_index(), 1234);
+//! received.do_cipher(&cipher);
+//! assert_eq!(received.data()[0], 42);
+//! ```
+
+use core::fmt;
+
+use crate::gf256::{self, EXP};
+use crate::{alg1, WireError};
+
+/// The longest codeword, data and check bytes together, that GF(2^8) can address
+pub const MAX_CODEWORD_LEN: usize = 255;
+
+/// The number of check bytes added to each [`alg1::IndexedBlock`] by [`encode_block`]. Up to 8
+/// corrupted bytes per frame are corrected
+pub const BLOCK_PARITY: usize = 16;
+
+/// The size of a frame written by [`encode_block`]
+pub const BLOCK_FRAME_SIZE: usize = alg1::IndexedBlock::WIRE_SIZE + BLOCK_PARITY;
+
+/// Errors returned when encoding or decoding Reed-Solomon codewords
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FecError {
+    /// The codeword is longer than [`MAX_CODEWORD_LEN`] bytes, or shorter than its check bytes
+    InvalidLength { len: usize },
+    /// A frame did not have the expected length
+    Wire(WireError),
+    /// More bytes were corrupted than the code can correct. The codeword is left unchanged
+    Uncorrectable,
+}
+
+impl fmt::Display for FecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FecError::InvalidLength { len } => {
+                write!(f, "invalid Reed-Solomon codeword length {}", len)
+            }
+            FecError::Wire(e) => write!(f, "{}", e),
+            FecError::Uncorrectable => write!(f, "too many errors to correct"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FecError {}
+
+impl From<WireError> for FecError {
+    fn from(e: WireError) -> Self {
+        FecError::Wire(e)
+    }
+}
+
+/// A Reed-Solomon code with `PARITY` check bytes, correcting up to `PARITY / 2` byte errors.
+///
+/// The generator polynomial has the roots `3^0` through `3^(PARITY - 1)` in GF(2^8) with the AES
+/// reduction polynomial, and the first byte of a codeword is the highest degree coefficient
+#[derive(Debug, Clone)]
+pub struct ReedSolomon<const PARITY: usize> {
+    /// The coefficients of the generator polynomial below its leading 1, highest degree first
+    generator: [u8; PARITY],
+}
+
+/// A polynomial with coefficients in GF(2^8), lowest degree first
+type Poly = [u8; MAX_CODEWORD_LEN + 1];
+
+impl<const PARITY: usize> ReedSolomon<PARITY> {
+    /// Creates the code.
+    ///
+    /// # Panics
+    /// If `PARITY` is zero or larger than 254
+    pub fn new() -> Self {
+        assert!(
+            PARITY > 0 && PARITY < MAX_CODEWORD_LEN,
+            "Reed-Solomon codes need between 1 and 254 check bytes"
+        );
+        // Multiply (x - 3^0)(x - 3^1)...(x - 3^(PARITY - 1)) together one root at a time. After
+        // `j` roots, `generator[i - 1]` holds the coefficient of x^(j - i)
+        let mut generator = [0u8; PARITY];
+        for (j, &root) in EXP[..PARITY].iter().enumerate() {
+            for i in (1..=j + 1).rev() {
+                let higher = if i == 1 { 1 } else { generator[i - 2] };
+                let current = if i <= j { generator[i - 1] } else { 0 };
+                generator[i - 1] = current ^ gf256::mul(root, higher);
+            }
+        }
+        Self { generator }
+    }
+
+    /// Computes the check bytes for `data`, which are appended to it to form a codeword
+    ///
+    /// Returns Err if the codeword would be longer than [`MAX_CODEWORD_LEN`]
+    pub fn encode(&self, data: &[u8]) -> Result<[u8; PARITY], FecError> {
+        let len = data.len() + PARITY;
+        if len > MAX_CODEWORD_LEN {
+            return Err(FecError::InvalidLength { len });
+        }
+        // The remainder of data(x) * x^PARITY divided by the generator
+        let mut remainder = [0u8; PARITY];
+        for &byte in data {
+            let factor = byte ^ remainder[0];
+            remainder.copy_within(1.., 0);
+            remainder[PARITY - 1] = 0;
+            for (r, &g) in remainder.iter_mut().zip(&self.generator) {
+                *r ^= gf256::mul(g, factor);
+            }
+        }
+        Ok(remainder)
+    }
+
+    /// Corrects `codeword`, data followed by the check bytes from [`ReedSolomon::encode`], in
+    /// place, returning the number of bytes that were corrected.
+    ///
+    /// Returns Err and leaves `codeword` unchanged if it has more errors than can be corrected, or
+    /// an invalid length
+    pub fn decode(&self, codeword: &mut [u8]) -> Result<usize, FecError> {
+        let len = codeword.len();
+        if len > MAX_CODEWORD_LEN || len < PARITY {
+            return Err(FecError::InvalidLength { len });
+        }
+        let mut syndromes = [0u8; PARITY];
+        if !self.syndromes(codeword, &mut syndromes) {
+            return Ok(0);
+        }
+
+        let (locator, errors) = berlekamp_massey(&syndromes);
+        if errors > PARITY / 2 {
+            return Err(FecError::Uncorrectable);
+        }
+
+        // The error evaluator, syndromes(x) * locator(x) mod x^PARITY
+        let mut evaluator = [0u8; PARITY];
+        for (i, e) in evaluator.iter_mut().enumerate() {
+            for j in 0..=i {
+                *e ^= gf256::mul(syndromes[j], locator[i - j]);
+            }
+        }
+
+        // Find the roots of the locator with a Chien search. Byte `i` of the codeword is the
+        // coefficient of x^(len - 1 - i), so an error there has locator X = 3^(len - 1 - i) and
+        // the locator has a root at X^-1. The magnitude follows from Forney's algorithm
+        let mut corrections = [(0usize, 0u8); MAX_CODEWORD_LEN / 2];
+        let mut found = 0;
+        for i in 0..len {
+            let power = len - 1 - i;
+            let x_inv = EXP[(255 - power) % 255];
+            if evaluate(&locator[..=errors], x_inv) != 0 {
+                continue;
+            }
+            if found == errors {
+                return Err(FecError::Uncorrectable);
+            }
+            // The formal derivative of the locator only keeps odd powers in GF(2^8)
+            let mut derivative = 0u8;
+            let mut x_power = 1u8;
+            for (k, &coefficient) in locator[1..=errors].iter().enumerate() {
+                if k % 2 == 0 {
+                    derivative ^= gf256::mul(coefficient, x_power);
+                }
+                x_power = gf256::mul(x_power, x_inv);
+            }
+            if derivative == 0 {
+                return Err(FecError::Uncorrectable);
+            }
+            let x = EXP[power];
+            let magnitude = gf256::div(gf256::mul(x, evaluate(&evaluator, x_inv)), derivative);
+            corrections[found] = (i, magnitude);
+            found += 1;
+        }
+        if found != errors {
+            return Err(FecError::Uncorrectable);
+        }
+
+        for &(i, magnitude) in &corrections[..found] {
+            codeword[i] ^= magnitude;
+        }
+        if self.syndromes(codeword, &mut syndromes) {
+            // Undo the miscorrection so the caller gets back what it passed in
+            for &(i, magnitude) in &corrections[..found] {
+                codeword[i] ^= magnitude;
+            }
+            return Err(FecError::Uncorrectable);
+        }
+        Ok(found)
+    }
+
+    /// Evaluates `codeword` at each root of the generator, returning true if any is non zero
+    fn syndromes(&self, codeword: &[u8], syndromes: &mut [u8; PARITY]) -> bool {
+        for (syndrome, &root) in syndromes.iter_mut().zip(&EXP) {
+            *syndrome = codeword
+                .iter()
+                .fold(0u8, |acc, &c| gf256::mul(acc, root) ^ c);
+        }
+        syndromes.iter().any(|&s| s != 0)
+    }
+}
+
+impl<const PARITY: usize> Default for ReedSolomon<PARITY> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Evaluates `poly`, lowest degree first, at `x`
+fn evaluate(poly: &[u8], x: u8) -> u8 {
+    poly.iter()
+        .rev()
+        .fold(0u8, |acc, &c| gf256::mul(acc, x) ^ c)
+}
+
+/// Finds the shortest error locator polynomial generating `syndromes`, returning it together with
+/// its degree, the number of errors
+fn berlekamp_massey(syndromes: &[u8]) -> (Poly, usize) {
+    let mut locator: Poly = [0; MAX_CODEWORD_LEN + 1];
+    let mut previous: Poly = [0; MAX_CODEWORD_LEN + 1];
+    locator[0] = 1;
+    previous[0] = 1;
+    let mut errors = 0;
+    let mut shift = 1;
+    let mut previous_discrepancy = 1u8;
+
+    for n in 0..syndromes.len() {
+        let mut discrepancy = syndromes[n];
+        for i in 1..=errors {
+            discrepancy ^= gf256::mul(locator[i], syndromes[n - i]);
+        }
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = gf256::div(discrepancy, previous_discrepancy);
+        let old = locator;
+        for (l, &p) in locator[shift..].iter_mut().zip(&previous) {
+            *l ^= gf256::mul(scale, p);
+        }
+        if 2 * errors <= n {
+            errors = n + 1 - errors;
+            previous = old;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+    }
+    (locator, errors)
+}
+
+/// Encodes the wire format of `block` followed by [`BLOCK_PARITY`] check bytes
+pub fn encode_block(block: &alg1::IndexedBlock) -> [u8; BLOCK_FRAME_SIZE] {
+    let mut frame = [0u8; BLOCK_FRAME_SIZE];
+    let (wire, parity) = frame.split_at_mut(alg1::IndexedBlock::WIRE_SIZE);
+    wire.copy_from_slice(&block.to_wire_bytes());
+    let code = ReedSolomon::<BLOCK_PARITY>::new();
+    parity.copy_from_slice(&code.encode(wire).unwrap());
+    frame
+}
+
+/// Corrects and parses a frame written by [`encode_block`], returning the block and the number of
+/// bytes that were corrected
+///
+/// Returns Err if `frame` is not [`BLOCK_FRAME_SIZE`] bytes long, or has too many errors to correct
+pub fn decode_block(frame: &[u8]) -> Result<(alg1::IndexedBlock, usize), FecError> {
+    crate::wire::check_length(frame, BLOCK_FRAME_SIZE)?;
+    let mut codeword = [0u8; BLOCK_FRAME_SIZE];
+    codeword.copy_from_slice(frame);
+    let corrected = ReedSolomon::<BLOCK_PARITY>::new().decode(&mut codeword)?;
+    let block = alg1::IndexedBlock::from_wire_bytes(&codeword[..alg1::IndexedBlock::WIRE_SIZE])?;
+    Ok((block, corrected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tag;
+    use rand::{Rng, SeedableRng};
+    use std::vec;
+
+    /// Corrupts `count` distinct bytes of `codeword`
+    fn corrupt(rng: &mut impl Rng, codeword: &mut [u8], count: usize) {
+        let positions = rand::seq::index::sample(rng, codeword.len(), count);
+        for i in positions {
+            codeword[i] ^= rng.gen_range(1..=255u8);
+        }
+    }
+
+    #[test]
+    fn generator_roots() {
+        let code = ReedSolomon::<4>::new();
+        // The check bytes of a single 1 are the generator below its leading 1, so a codeword is
+        // the generator itself and has every root as a zero
+        let parity = code.encode(&[1]).unwrap();
+        assert_eq!(parity, code.generator);
+        let mut codeword = [1, parity[0], parity[1], parity[2], parity[3]];
+        for &root in &EXP[..4] {
+            assert_eq!(
+                codeword.iter().fold(0, |acc, &c| gf256::mul(acc, root) ^ c),
+                0
+            );
+        }
+        assert_eq!(code.decode(&mut codeword), Ok(0));
+    }
+
+    #[test]
+    fn corrects_up_to_half_parity() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let code = ReedSolomon::<10>::new();
+        for len in [1, 20, 100, 245] {
+            for errors in 0..=5 {
+                let mut data = vec![0u8; len];
+                rng.fill(data.as_mut_slice());
+                let mut codeword = data.clone();
+                codeword.extend_from_slice(&code.encode(&data).unwrap());
+                let original = codeword.clone();
+
+                corrupt(&mut rng, &mut codeword, errors);
+                assert_eq!(code.decode(&mut codeword), Ok(errors));
+                assert_eq!(codeword, original);
+            }
+        }
+    }
+
+    #[test]
+    fn detects_too_many_errors() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let code = ReedSolomon::<BLOCK_PARITY>::new();
+        let mut detected = 0;
+        for _ in 0..100 {
+            let mut codeword = [0u8; BLOCK_FRAME_SIZE];
+            rng.fill(&mut codeword[..32]);
+            let parity = code.encode(&codeword[..32]).unwrap();
+            codeword[32..].copy_from_slice(&parity);
+
+            corrupt(&mut rng, &mut codeword, BLOCK_PARITY / 2 + 1);
+            let corrupted = codeword;
+            if code.decode(&mut codeword) == Err(FecError::Uncorrectable) {
+                assert_eq!(codeword, corrupted);
+                detected += 1;
+            }
+        }
+        assert!(detected >= 95, "only {} of 100 detected", detected);
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        let code = ReedSolomon::<8>::new();
+        assert!(code.encode(&[0; 247]).is_ok());
+        assert_eq!(
+            code.encode(&[0; 248]),
+            Err(FecError::InvalidLength { len: 256 })
+        );
+        assert_eq!(
+            code.decode(&mut [0; 7]),
+            Err(FecError::InvalidLength { len: 7 })
+        );
+        assert_eq!(
+            decode_block(&[0; 47]).err(),
+            Some(FecError::Wire(WireError::WrongLength {
+                expected: 48,
+                actual: 47
+            }))
+        );
+    }
+
+    #[test]
+    fn corrupted_tags() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let mut block = alg1::IndexedBlock::new();
+        rng.fill(block.data_mut());
+        block.tag().set_index(0x1234_5678);
+        block.tag().set_tag(1);
+        let frame = encode_block(&block);
+        assert_eq!(frame[..32], block.to_wire_bytes());
+
+        for bit in 0..32 {
+            let mut noisy = frame;
+            noisy[bit / 8] ^= 1 << (bit % 8);
+            let (mut received, corrected) = decode_block(&noisy).unwrap();
+            assert_eq!(corrected, 1);
+            assert_eq!(received.tag().get_index(), 0x1234_5678);
+            assert_eq!(received.tag().get_tag(), 1);
+            assert_eq!(received.data(), block.data());
+        }
+
+        // Every byte of the tag and four more anywhere else
+        let mut noisy = frame;
+        for byte in noisy[..4].iter_mut() {
+            *byte = !*byte;
+        }
+        corrupt(&mut rng, &mut noisy[4..], 4);
+        let (received, corrected) = decode_block(&noisy).unwrap();
+        assert_eq!(corrected, 8);
+        assert_eq!(received.to_wire_bytes(), block.to_wire_bytes());
+    }
+}
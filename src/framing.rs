@@ -0,0 +1,789 @@
+//! Splitting messages that do not fit in a single block into fragments, and reassembling them on
+//! the receiving side.
+//!
+//! Each fragment starts with a [`HEADER_LEN`] byte header inside the block data, followed by
+//! the fragment payload:
+//!
+//! | Bytes | Field                                                 |
+//! |-------|-------------------------------------------------------|
+//! | 0..4  | Message ID (little endian)                            |
+//! | 4..6  | Fragment number, starting at 0 (little endian)        |
+//! | 6..8  | Fragment count (little endian)                        |
+//! | 8     | Number of payload bytes used in this fragment         |
+//! | 9     | Flags. Bit 0 is set on the last fragment of a message |
+//!
+//! The header is inside the encrypted data, so it is hidden on the wire. The tag bits of the block
+//! are left free for other uses such as key IDs.
+//!
+//! ```
+//! use encryption::{Key, Tag, alg1::{Algorithm1, IndexedBlock}};
+//! use encryption::framing::{self, PushOutcome, Reassembler, ReassemblyConfig};
+//! use std::time::Instant;
+//!
+//! let key = Key::new([9u8; 64]);
+//! let cipher = Algorithm1::new(&key, 0x5555);
+//! let message = b"A message that is much longer than a single 28 byte block";
+//!
+//! let mut blocks: Vec<IndexedBlock> = framing::fragment(7, message).unwrap();
+//! for (index, block) in blocks.iter_mut().enumerate() {
+//!     // Every block needs its own index
+//!     block.tag().set_index(index as u32);
+//!     block.do_cipher(&cipher);
+//! }
+//!
+//! let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+//! let mut outcome = PushOutcome::Pending;
+//! for mut block in blocks.into_iter().rev() {
+//!     block.do_cipher(&cipher);
+//!     outcome = reassembler.push(&block, Instant::now()).unwrap();
+//! }
+//! assert_eq!(outcome, PushOutcome::Complete { message_id: 7, message: message.to_vec() });
+//! ```
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::size_of;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{GenericIndexedBlock, Tag, Word};
+
+/// The length of the fragment header at the start of each block's data
+pub const HEADER_LEN: usize = 10;
+
+const LAST_FRAGMENT: u8 = 1;
+
+/// A block type that can carry fragments
+pub trait FrameBlock: Sized {
+    /// The number of data bytes in the block, including the fragment header
+    const DATA_BYTES: usize;
+
+    /// Creates a block with index 0 and the given data bytes
+    ///
+    /// `bytes` is exactly [`FrameBlock::DATA_BYTES`] long
+    fn with_data_bytes(bytes: &[u8]) -> Self;
+
+    /// Copies the data bytes of this block into `out`
+    ///
+    /// `out` is exactly [`FrameBlock::DATA_BYTES`] long
+    fn copy_data_bytes(&self, out: &mut [u8]);
+}
+
+impl<T: Tag + Default, W: Word, const N: usize, A> FrameBlock for GenericIndexedBlock<T, W, N, A> {
+    const DATA_BYTES: usize = Self::DATA_SIZE;
+
+    fn with_data_bytes(bytes: &[u8]) -> Self {
+        let mut block = Self::new();
+        block.set_data_bytes(bytes).unwrap();
+        block
+    }
+
+    fn copy_data_bytes(&self, out: &mut [u8]) {
+        out.copy_from_slice(&self.data_bytes());
+    }
+}
+
+/// Returns the number of message bytes carried by each fragment in blocks of type `B`.
+///
+/// Blocks must have room for at least one payload byte after the header, and at most 255, the
+/// largest length the header can hold. Other block types fail to compile:
+///
+/// ```compile_fail
+/// use encryption::{framing, GenericIndexedBlock, Tag31_1};
+///
+/// // 8 data bytes cannot hold the 10 byte header
+/// framing::payload_len::<GenericIndexedBlock<Tag31_1, u32, 2>>();
+/// ```
+///
+/// ```compile_fail
+/// use encryption::{framing, GenericIndexedBlock, Tag56_8};
+///
+/// // 262 payload bytes do not fit in the length byte
+/// framing::payload_len::<GenericIndexedBlock<Tag56_8, u64, 34>>();
+/// ```
+pub const fn payload_len<B: FrameBlock>() -> usize {
+    Layout::<B>::PAYLOAD_LEN
+}
+
+/// Checks the layout of a [`FrameBlock`] when it is used, since it is not known before then
+struct Layout<B>(PhantomData<B>);
+
+impl<B: FrameBlock> Layout<B> {
+    const PAYLOAD_LEN: usize = {
+        assert!(
+            B::DATA_BYTES > HEADER_LEN,
+            "Blocks must have data bytes left over after the fragment header"
+        );
+        // The length of each fragment is stored in a single byte
+        assert!(
+            B::DATA_BYTES - HEADER_LEN <= u8::MAX as usize,
+            "Fragment payloads must fit in the length byte of the header"
+        );
+        B::DATA_BYTES - HEADER_LEN
+    };
+}
+
+/// Errors returned when fragmenting or reassembling messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramingError {
+    /// The message needs more than `u16::MAX` fragments
+    MessageTooLarge { len: usize },
+    /// A fragment header is inconsistent with itself or with earlier fragments of the same
+    /// message. This usually means the block was decrypted with the wrong key or index
+    InvalidHeader { message_id: u32 },
+    /// Storing the fragment would exceed [`ReassemblyConfig::max_memory`]. The fragment was
+    /// discarded
+    MemoryLimit { message_id: u32 },
+    /// The fragment header claims more than [`ReassemblyConfig::max_fragments`] fragments. The
+    /// fragment was discarded
+    TooManyFragments { message_id: u32 },
+    /// Starting the message would hold more than [`ReassemblyConfig::max_pending`] incomplete
+    /// messages. The fragment was discarded
+    TooManyPending { message_id: u32 },
+}
+
+impl fmt::Display for FramingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FramingError::MessageTooLarge { len } => {
+                write!(f, "message of {} bytes needs too many fragments", len)
+            }
+            FramingError::InvalidHeader { message_id } => {
+                write!(f, "invalid fragment header for message {}", message_id)
+            }
+            FramingError::MemoryLimit { message_id } => write!(
+                f,
+                "reassembly memory limit reached, dropped fragment of message {}",
+                message_id
+            ),
+            FramingError::TooManyFragments { message_id } => write!(
+                f,
+                "message {} has more fragments than allowed, dropped fragment",
+                message_id
+            ),
+            FramingError::TooManyPending { message_id } => write!(
+                f,
+                "too many incomplete messages, dropped fragment of message {}",
+                message_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FramingError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FragmentHeader {
+    message_id: u32,
+    fragment: u16,
+    count: u16,
+    len: u8,
+    flags: u8,
+}
+
+impl FragmentHeader {
+    fn write(&self, out: &mut [u8]) {
+        out[0..4].copy_from_slice(&self.message_id.to_le_bytes());
+        out[4..6].copy_from_slice(&self.fragment.to_le_bytes());
+        out[6..8].copy_from_slice(&self.count.to_le_bytes());
+        out[8] = self.len;
+        out[9] = self.flags;
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        Self {
+            message_id: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            fragment: u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            count: u16::from_le_bytes(bytes[6..8].try_into().unwrap()),
+            len: bytes[8],
+            flags: bytes[9],
+        }
+    }
+
+    /// Checks that this header is self consistent for fragments holding up to `payload_len` bytes
+    fn is_valid(&self, payload_len: usize) -> bool {
+        let is_last = self.fragment as u32 + 1 == self.count as u32;
+        self.count != 0
+            && self.fragment < self.count
+            && self.len as usize <= payload_len
+            && (self.flags & LAST_FRAGMENT != 0) == is_last
+            && self.flags & !LAST_FRAGMENT == 0
+            // Only the last fragment may be partially filled
+            && (is_last || self.len as usize == payload_len)
+    }
+}
+
+/// Splits `message` into plaintext blocks carrying fragments of message `message_id`.
+///
+/// The returned blocks have index 0. Each one must be given a unique index before it is encrypted.
+/// An empty message produces a single fragment
+pub fn fragment<B: FrameBlock>(message_id: u32, message: &[u8]) -> Result<Vec<B>, FramingError> {
+    let payload_len = payload_len::<B>();
+
+    let count = message.len().div_ceil(payload_len).max(1);
+    let count: u16 = count
+        .try_into()
+        .map_err(|_| FramingError::MessageTooLarge { len: message.len() })?;
+
+    let mut data = vec![0u8; B::DATA_BYTES];
+    let blocks = (0..count)
+        .map(|fragment| {
+            let start = fragment as usize * payload_len;
+            let end = (start + payload_len).min(message.len());
+            let payload = &message[start..end];
+            let is_last = fragment + 1 == count;
+
+            data.fill(0);
+            FragmentHeader {
+                message_id,
+                fragment,
+                count,
+                len: payload.len() as u8,
+                flags: if is_last { LAST_FRAGMENT } else { 0 },
+            }
+            .write(&mut data);
+            data[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(payload);
+            B::with_data_bytes(&data)
+        })
+        .collect();
+    Ok(blocks)
+}
+
+/// Limits on the resources used by a [`Reassembler`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReassemblyConfig {
+    /// Incomplete messages are dropped by [`Reassembler::expire`] once this much time has passed
+    /// since their first fragment arrived
+    pub timeout: Duration,
+    /// The maximum number of bytes held for all incomplete messages together, and for the IDs of
+    /// completed messages that have not expired. Each incomplete message is charged for its
+    /// fragment table up front, so a forged fragment count cannot allocate past this limit
+    pub max_memory: usize,
+    /// The maximum number of fragments a message may have
+    pub max_fragments: u16,
+    /// The maximum number of incomplete messages held at once
+    pub max_pending: usize,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_memory: 1024 * 1024,
+            max_fragments: 1024,
+            max_pending: 64,
+        }
+    }
+}
+
+/// The result of passing a fragment to [`Reassembler::push`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PushOutcome {
+    /// The fragment completed its message
+    Complete { message_id: u32, message: Vec<u8> },
+    /// The fragment was stored and more fragments are needed
+    Pending,
+    /// The fragment had already been received and was ignored
+    Duplicate,
+}
+
+/// The number of bytes charged against [`ReassemblyConfig::max_memory`] for each fragment of an
+/// incomplete message, whether or not it has arrived
+const SLOT_SIZE: usize = size_of::<Option<Vec<u8>>>();
+
+/// The number of bytes charged against [`ReassemblyConfig::max_memory`] for remembering the ID of
+/// a completed message
+const COMPLETED_SIZE: usize = size_of::<(u32, Instant)>();
+
+struct PartialMessage {
+    first_seen: Instant,
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+    /// The bytes charged for this message, including the entry it takes in `completed` once done
+    bytes: usize,
+}
+
+/// Collects fragments from decrypted blocks and returns complete messages.
+///
+/// Fragments may arrive in any order and more than once. Messages with missing fragments are held
+/// until they are dropped by [`Reassembler::expire`]. The IDs of completed messages are remembered
+/// for the same timeout, so that late duplicates do not deliver a message twice
+pub struct Reassembler<B: FrameBlock> {
+    config: ReassemblyConfig,
+    partial: HashMap<u32, PartialMessage>,
+    completed: HashMap<u32, Instant>,
+    memory_used: usize,
+    _block: PhantomData<fn(&B)>,
+}
+
+impl<B: FrameBlock> Reassembler<B> {
+    pub fn new(config: ReassemblyConfig) -> Self {
+        Self {
+            config,
+            partial: HashMap::new(),
+            completed: HashMap::new(),
+            memory_used: 0,
+            _block: PhantomData,
+        }
+    }
+
+    /// Adds the fragment in the decrypted `block`, which was received at `now`
+    pub fn push(&mut self, block: &B, now: Instant) -> Result<PushOutcome, FramingError> {
+        let payload_len = payload_len::<B>();
+        let mut data = vec![0u8; B::DATA_BYTES];
+        block.copy_data_bytes(&mut data);
+        let header = FragmentHeader::read(&data);
+        let message_id = header.message_id;
+        if !header.is_valid(payload_len) {
+            return Err(FramingError::InvalidHeader { message_id });
+        }
+        let payload = &data[HEADER_LEN..HEADER_LEN + header.len as usize];
+
+        if self.completed.contains_key(&message_id) {
+            return Ok(PushOutcome::Duplicate);
+        }
+        if header.count > self.config.max_fragments {
+            return Err(FramingError::TooManyFragments { message_id });
+        }
+        if header.count == 1 {
+            // Earlier fragments of a message with this ID can no longer complete, so drop them
+            // instead of holding them until they expire
+            if let Some(stale) = self.partial.remove(&message_id) {
+                self.memory_used -= stale.bytes;
+            }
+            self.charge(COMPLETED_SIZE, message_id)?;
+            self.completed.insert(message_id, now);
+            return Ok(PushOutcome::Complete {
+                message_id,
+                message: payload.to_vec(),
+            });
+        }
+
+        let cost = match self.partial.get(&message_id) {
+            Some(partial) => {
+                if partial.fragments.len() != header.count as usize {
+                    return Err(FramingError::InvalidHeader { message_id });
+                }
+                if partial.fragments[header.fragment as usize].is_some() {
+                    return Ok(PushOutcome::Duplicate);
+                }
+                payload.len()
+            }
+            None => {
+                if self.partial.len() >= self.config.max_pending {
+                    return Err(FramingError::TooManyPending { message_id });
+                }
+                // Completing the message must not fail, so its entry in `completed` is charged now
+                header.count as usize * SLOT_SIZE + COMPLETED_SIZE + payload.len()
+            }
+        };
+        self.charge(cost, message_id)?;
+
+        let partial = self
+            .partial
+            .entry(message_id)
+            .or_insert_with(|| PartialMessage {
+                first_seen: now,
+                fragments: vec![None; header.count as usize],
+                received: 0,
+                bytes: 0,
+            });
+        partial.fragments[header.fragment as usize] = Some(payload.to_vec());
+        partial.received += 1;
+        partial.bytes += cost;
+
+        if partial.received < partial.fragments.len() {
+            return Ok(PushOutcome::Pending);
+        }
+        let partial = self.partial.remove(&message_id).unwrap();
+        self.memory_used -= partial.bytes - COMPLETED_SIZE;
+        self.completed.insert(message_id, now);
+        let message = partial.fragments.into_iter().flatten().flatten().collect();
+        Ok(PushOutcome::Complete {
+            message_id,
+            message,
+        })
+    }
+
+    /// Drops every incomplete message whose first fragment arrived more than
+    /// [`ReassemblyConfig::timeout`] before `now`, returning their message IDs
+    ///
+    /// Completed messages older than the timeout are also forgotten, after which their IDs may be
+    /// reused
+    pub fn expire(&mut self, now: Instant) -> Vec<u32> {
+        let timeout = self.config.timeout;
+        let completed = self.completed.len();
+        self.completed
+            .retain(|_, &mut completed| now.saturating_duration_since(completed) <= timeout);
+        self.memory_used -= (completed - self.completed.len()) * COMPLETED_SIZE;
+        let expired: Vec<u32> = self
+            .partial
+            .iter()
+            .filter(|(_, partial)| now.saturating_duration_since(partial.first_seen) > timeout)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &expired {
+            let partial = self.partial.remove(id).unwrap();
+            self.memory_used -= partial.bytes;
+        }
+        expired
+    }
+
+    /// Returns the fragment numbers still missing from the incomplete message `message_id`, or
+    /// None if no fragments of it are held
+    pub fn missing(&self, message_id: u32) -> Option<Vec<u16>> {
+        self.partial.get(&message_id).map(|partial| {
+            (0..partial.fragments.len() as u16)
+                .filter(|&i| partial.fragments[i as usize].is_none())
+                .collect()
+        })
+    }
+
+    /// Returns the number of incomplete messages held
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Returns the number of bytes charged against [`ReassemblyConfig::max_memory`]
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    /// Charges `bytes` against [`ReassemblyConfig::max_memory`], or returns Err if that would
+    /// exceed it
+    fn charge(&mut self, bytes: usize, message_id: u32) -> Result<(), FramingError> {
+        if self.memory_used + bytes > self.config.max_memory {
+            return Err(FramingError::MemoryLimit { message_id });
+        }
+        self.memory_used += bytes;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alg1, alg2, alg3, Key};
+    use rand::seq::SliceRandom;
+    use rand::{RngCore, SeedableRng};
+
+    #[test]
+    fn fragment_layout() {
+        let message: Vec<u8> = (0..40).collect();
+        let blocks: Vec<alg1::IndexedBlock> = fragment(0x0102_0304, &message).unwrap();
+        assert_eq!(payload_len::<alg1::IndexedBlock>(), 18);
+        assert_eq!(payload_len::<alg3::IndexedBlock>(), 46);
+        assert_eq!(blocks.len(), 3);
+
+        let first = blocks[0].data_bytes();
+        assert_eq!(first[..HEADER_LEN], [4, 3, 2, 1, 0, 0, 3, 0, 18, 0]);
+        assert_eq!(first[HEADER_LEN..], message[..18]);
+
+        let last = blocks[2].data_bytes();
+        assert_eq!(last[..HEADER_LEN], [4, 3, 2, 1, 2, 0, 3, 0, 4, 1]);
+        assert_eq!(last[HEADER_LEN..HEADER_LEN + 4], message[36..]);
+        assert!(last[HEADER_LEN + 4..].iter().all(|&b| b == 0));
+
+        let empty: Vec<alg2::IndexedBlock> = fragment(1, &[]).unwrap();
+        assert_eq!(empty.len(), 1);
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        assert_eq!(
+            reassembler.push(&empty[0], Instant::now()),
+            Ok(PushOutcome::Complete {
+                message_id: 1,
+                message: vec![]
+            })
+        );
+    }
+
+    #[test]
+    fn too_large() {
+        let message = vec![0u8; 18 * u16::MAX as usize + 1];
+        assert_eq!(
+            fragment::<alg1::IndexedBlock>(0, &message).err(),
+            Some(FramingError::MessageTooLarge { len: message.len() })
+        );
+        assert!(fragment::<alg1::IndexedBlock>(0, &message[1..]).is_ok());
+    }
+
+    #[test]
+    fn out_of_order_and_duplicates() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut key_bytes = [0u8; 4096];
+        rng.fill_bytes(&mut key_bytes);
+        let key = Key::new(key_bytes);
+        let cipher = alg2::Algorithm2::new(&key, rng.next_u64());
+
+        let mut messages = Vec::new();
+        let mut wire = Vec::new();
+        let mut index = 0;
+        for message_id in 0..5 {
+            let mut message = vec![0u8; 300 * message_id as usize + 17];
+            rng.fill_bytes(&mut message);
+            for mut block in fragment::<alg2::IndexedBlock>(message_id, &message).unwrap() {
+                block.tag().set_index(index);
+                index += 1;
+                block.do_cipher(&cipher);
+                wire.push(block.to_wire_bytes());
+            }
+            messages.push(message);
+        }
+        // Send some blocks twice, then shuffle everything
+        let duplicates: Vec<_> = wire.iter().step_by(3).cloned().collect();
+        wire.extend(duplicates);
+        wire.shuffle(&mut rng);
+
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let now = Instant::now();
+        let mut complete = HashMap::new();
+        let mut duplicate_count = 0;
+        for bytes in &wire {
+            let mut block = alg2::IndexedBlock::from_wire_bytes(bytes).unwrap();
+            block.do_cipher(&cipher);
+            match reassembler.push(&block, now).unwrap() {
+                PushOutcome::Complete {
+                    message_id,
+                    message,
+                } => assert!(complete.insert(message_id, message).is_none()),
+                PushOutcome::Pending => {}
+                PushOutcome::Duplicate => duplicate_count += 1,
+            }
+        }
+        assert!(duplicate_count > 0);
+        assert_eq!(complete.len(), messages.len());
+        for (id, message) in messages.iter().enumerate() {
+            assert_eq!(&complete[&(id as u32)], message);
+        }
+        assert_eq!(reassembler.pending(), 0);
+        assert_eq!(reassembler.memory_used(), messages.len() * COMPLETED_SIZE);
+        reassembler.expire(now + Duration::from_secs(60));
+        assert_eq!(reassembler.memory_used(), 0);
+    }
+
+    #[test]
+    fn missing_fragments_expire() {
+        let config = ReassemblyConfig {
+            timeout: Duration::from_secs(5),
+            max_memory: 1024,
+            ..ReassemblyConfig::default()
+        };
+        let mut reassembler = Reassembler::new(config);
+        let blocks: Vec<alg1::IndexedBlock> = fragment(3, &[1u8; 50]).unwrap();
+        assert_eq!(blocks.len(), 3);
+
+        let start = Instant::now();
+        assert_eq!(
+            reassembler.push(&blocks[0], start),
+            Ok(PushOutcome::Pending)
+        );
+        assert_eq!(
+            reassembler.push(&blocks[2], start),
+            Ok(PushOutcome::Pending)
+        );
+        assert_eq!(reassembler.missing(3), Some(vec![1]));
+        assert_eq!(
+            reassembler.memory_used(),
+            3 * SLOT_SIZE + COMPLETED_SIZE + 18 + 14
+        );
+
+        assert!(reassembler
+            .expire(start + Duration::from_secs(4))
+            .is_empty());
+        assert_eq!(reassembler.expire(start + Duration::from_secs(6)), vec![3]);
+        assert_eq!(reassembler.pending(), 0);
+        assert_eq!(reassembler.memory_used(), 0);
+        assert_eq!(reassembler.missing(3), None);
+
+        // Late duplicates of a completed message are ignored until it expires
+        let single: Vec<alg1::IndexedBlock> = fragment(4, &[4u8; 5]).unwrap();
+        let complete = Ok(PushOutcome::Complete {
+            message_id: 4,
+            message: vec![4; 5],
+        });
+        assert_eq!(reassembler.push(&single[0], start), complete);
+        assert_eq!(
+            reassembler.push(&single[0], start),
+            Ok(PushOutcome::Duplicate)
+        );
+        reassembler.expire(start + Duration::from_secs(6));
+        assert_eq!(
+            reassembler.push(&single[0], start + Duration::from_secs(6)),
+            complete
+        );
+    }
+
+    #[test]
+    fn single_fragment_replaces_partial() {
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let now = Instant::now();
+        let partial: Vec<alg1::IndexedBlock> = fragment(6, &[1u8; 50]).unwrap();
+        assert_eq!(reassembler.push(&partial[0], now), Ok(PushOutcome::Pending));
+        assert_eq!(reassembler.pending(), 1);
+
+        let single: Vec<alg1::IndexedBlock> = fragment(6, &[2u8; 5]).unwrap();
+        assert_eq!(
+            reassembler.push(&single[0], now),
+            Ok(PushOutcome::Complete {
+                message_id: 6,
+                message: vec![2; 5]
+            })
+        );
+        assert_eq!(reassembler.pending(), 0);
+        assert_eq!(reassembler.missing(6), None);
+        assert_eq!(reassembler.memory_used(), COMPLETED_SIZE);
+        assert_eq!(
+            reassembler.push(&partial[1], now),
+            Ok(PushOutcome::Duplicate)
+        );
+    }
+
+    #[test]
+    fn memory_limit() {
+        // Each message holds a table of two fragments and reserves its completed entry
+        let overhead = 2 * SLOT_SIZE + COMPLETED_SIZE;
+        let config = ReassemblyConfig {
+            timeout: Duration::from_secs(5),
+            max_memory: 2 * overhead + 40,
+            ..ReassemblyConfig::default()
+        };
+        let mut reassembler = Reassembler::new(config);
+        let now = Instant::now();
+        let first: Vec<alg1::IndexedBlock> = fragment(1, &[1u8; 36]).unwrap();
+        let second: Vec<alg1::IndexedBlock> = fragment(2, &[2u8; 36]).unwrap();
+
+        assert_eq!(reassembler.push(&first[0], now), Ok(PushOutcome::Pending));
+        assert_eq!(reassembler.push(&second[0], now), Ok(PushOutcome::Pending));
+        assert_eq!(
+            reassembler.push(&first[1], now),
+            Err(FramingError::MemoryLimit { message_id: 1 })
+        );
+        assert_eq!(reassembler.memory_used(), 2 * overhead + 36);
+        reassembler.expire(now + Duration::from_secs(10));
+        assert_eq!(reassembler.push(&first[1], now), Ok(PushOutcome::Pending));
+    }
+
+    /// Returns the first fragment of message `message_id`, claiming `count` fragments
+    fn forged_fragment(message_id: u32, count: u16) -> alg1::IndexedBlock {
+        let blocks: Vec<alg1::IndexedBlock> = fragment(message_id, &[0u8; 36]).unwrap();
+        let mut data = blocks[0].data_bytes();
+        data[6..8].copy_from_slice(&count.to_le_bytes());
+        alg1::IndexedBlock::with_data_bytes(&data)
+    }
+
+    #[test]
+    fn forged_fragment_counts() {
+        let now = Instant::now();
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        assert_eq!(
+            reassembler.push(&forged_fragment(1, 1025), now),
+            Err(FramingError::TooManyFragments { message_id: 1 })
+        );
+
+        // Fragment tables are charged up front, so many large counts hit the memory limit before
+        // the tables are allocated
+        let config = ReassemblyConfig {
+            max_memory: 64 * 1024,
+            max_fragments: u16::MAX,
+            max_pending: usize::MAX,
+            ..ReassemblyConfig::default()
+        };
+        let mut reassembler = Reassembler::new(config);
+        let mut accepted = 0;
+        for message_id in 0..1000 {
+            match reassembler.push(&forged_fragment(message_id, u16::MAX), now) {
+                Ok(PushOutcome::Pending) => accepted += 1,
+                Err(FramingError::MemoryLimit { .. }) => {}
+                other => panic!("unexpected {:?}", other),
+            }
+            assert!(reassembler.memory_used() <= config.max_memory);
+        }
+        assert_eq!(accepted, 0);
+
+        // Smaller tables are accepted until the limit is reached
+        let mut reassembler = Reassembler::new(config);
+        for message_id in 0..1000 {
+            let _ = reassembler.push(&forged_fragment(message_id, 1000), now);
+            assert!(reassembler.memory_used() <= config.max_memory);
+        }
+        assert_eq!(
+            reassembler.pending(),
+            config.max_memory / (1000 * SLOT_SIZE + COMPLETED_SIZE + 18)
+        );
+
+        let mut reassembler = Reassembler::new(ReassemblyConfig {
+            max_pending: 2,
+            ..ReassemblyConfig::default()
+        });
+        for message_id in 0..2 {
+            assert_eq!(
+                reassembler.push(&forged_fragment(message_id, 3), now),
+                Ok(PushOutcome::Pending)
+            );
+        }
+        assert_eq!(
+            reassembler.push(&forged_fragment(2, 3), now),
+            Err(FramingError::TooManyPending { message_id: 2 })
+        );
+    }
+
+    #[test]
+    fn completed_ids_are_bounded() {
+        let config = ReassemblyConfig {
+            max_memory: 10 * COMPLETED_SIZE,
+            ..ReassemblyConfig::default()
+        };
+        let mut reassembler = Reassembler::new(config);
+        let now = Instant::now();
+        for message_id in 0..12 {
+            let block: Vec<alg1::IndexedBlock> = fragment(message_id, &[1]).unwrap();
+            let outcome = reassembler.push(&block[0], now);
+            if message_id < 10 {
+                assert!(matches!(outcome, Ok(PushOutcome::Complete { .. })));
+            } else {
+                assert_eq!(outcome, Err(FramingError::MemoryLimit { message_id }));
+            }
+        }
+        assert_eq!(reassembler.memory_used(), config.max_memory);
+        reassembler.expire(now + Duration::from_secs(60));
+        assert_eq!(reassembler.memory_used(), 0);
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let mut reassembler = Reassembler::new(ReassemblyConfig::default());
+        let now = Instant::now();
+        let valid: Vec<alg1::IndexedBlock> = fragment(9, &[0u8; 30]).unwrap();
+        let corrupt = |change: &dyn Fn(&mut [u8])| {
+            let mut data = valid[0].data_bytes();
+            change(&mut data);
+            alg1::IndexedBlock::with_data_bytes(&data)
+        };
+
+        let invalid = [
+            // Zero fragments
+            corrupt(&|d| d[6..8].copy_from_slice(&[0, 0])),
+            // Fragment number out of range
+            corrupt(&|d| d[4..6].copy_from_slice(&[2, 0])),
+            // Length longer than the payload
+            corrupt(&|d| d[8] = 19),
+            // Last fragment flag on the first fragment
+            corrupt(&|d| d[9] = LAST_FRAGMENT),
+            // Unknown flags
+            corrupt(&|d| d[9] = 0x80),
+        ];
+        for block in &invalid {
+            assert_eq!(
+                reassembler.push(block, now),
+                Err(FramingError::InvalidHeader { message_id: 9 })
+            );
+        }
+
+        // A different fragment count than earlier fragments of the same message
+        assert_eq!(reassembler.push(&valid[0], now), Ok(PushOutcome::Pending));
+        let other: Vec<alg1::IndexedBlock> = fragment(9, &[0u8; 60]).unwrap();
+        assert_eq!(
+            reassembler.push(&other[1], now),
+            Err(FramingError::InvalidHeader { message_id: 9 })
+        );
+    }
+}
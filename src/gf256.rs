@@ -0,0 +1,108 @@
+//! Arithmetic in the finite field GF(2^8), using the AES reduction polynomial
+//! `x^8 + x^4 + x^3 + x + 1` (0x11B) with generator 3.
+//!
+//! Addition and subtraction are both Xor, so only multiplication and division are provided here
+
+const POLY: u16 = 0x11B;
+const GENERATOR: u8 = 3;
+
+/// `EXP[i]` is `GENERATOR^i`. The table is doubled so that the sum of two logarithms can be used as
+/// an index without reducing modulo 255
+pub(crate) const EXP: [u8; 512] = exp_table();
+
+/// `LOG[x]` is the discrete logarithm of `x` base `GENERATOR`. `LOG[0]` is unused
+pub(crate) const LOG: [u8; 256] = log_table();
+
+const fn slow_mul(a: u8, b: u8) -> u8 {
+    let mut a = a as u16;
+    let mut b = b;
+    let mut result = 0u16;
+    while b != 0 {
+        if b & 1 != 0 {
+            result ^= a;
+        }
+        a <<= 1;
+        if a & 0x100 != 0 {
+            a ^= POLY;
+        }
+        b >>= 1;
+    }
+    result as u8
+}
+
+const fn exp_table() -> [u8; 512] {
+    let mut table = [0u8; 512];
+    let mut x = 1u8;
+    let mut i = 0;
+    while i < 512 {
+        table[i] = x;
+        x = slow_mul(x, GENERATOR);
+        i += 1;
+    }
+    table
+}
+
+const fn log_table() -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < 255 {
+        table[EXP[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+}
+
+/// Multiplies `a` and `b`
+pub(crate) fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    EXP[LOG[a as usize] as usize + LOG[b as usize] as usize]
+}
+
+/// Divides `a` by `b`
+///
+/// # Panics
+/// If `b` is zero
+pub(crate) fn div(a: u8, b: u8) -> u8 {
+    assert_ne!(b, 0, "Division by zero in GF(256)");
+    if a == 0 {
+        return 0;
+    }
+    EXP[LOG[a as usize] as usize + 255 - LOG[b as usize] as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_match_slow_mul() {
+        for a in (0..=255u8).step_by(crate::test_step(15)) {
+            for b in (0..=255u8).step_by(crate::test_step(15)) {
+                assert_eq!(mul(a, b), slow_mul(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn division_inverts_multiplication() {
+        for a in (0..=255u8).step_by(crate::test_step(15)) {
+            for b in (1..=255u8).step_by(crate::test_step(15)) {
+                assert_eq!(div(mul(a, b), b), a);
+            }
+        }
+        // Known inverse from the AES specification
+        assert_eq!(mul(0x53, 0xCA), 1);
+    }
+
+    #[test]
+    fn generator_is_primitive() {
+        let mut seen = [false; 256];
+        for &x in &EXP[..255] {
+            assert!(!seen[x as usize], "{} repeated", x);
+            seen[x as usize] = true;
+        }
+        assert!(!seen[0]);
+    }
+}
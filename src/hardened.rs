@@ -0,0 +1,281 @@
+//! An optional nonlinear layer applied after the Xor, so that known plaintext does not reveal the
+//! key.
+//!
+//! The plain algorithms Xor each block with a slice of the key selected by its index. Anyone who
+//! knows the plaintext of one block can Xor it with the ciphertext and recover those key words,
+//! which blocks at nearby indices reuse. [`Hardened`] wraps any [`WordCipher`] and follows the Xor
+//! with rounds of keyed byte substitution and word permutation. Each round is derived from a
+//! separate hardening key and the block index, so the pad is no longer exposed by one known block.
+//!
+//! The rounds are not an involution, so unlike [`WordCipher::cipher_words`], encryption and
+//! decryption are separate operations:
+//!
+//! ```
+//! use encryption::{Hardened, Key, Tag, alg1::{Algorithm1, IndexedBlock}};
+//!
+//! let key = Key::new([7u8; 64]);
+//! // Use a secret, randomly generated hardening key shared by both ends
+//! let cipher = Hardened::new(Algorithm1::new(&key, 0x1234), 0x0123_4567_89AB_CDEF);
+//!
+//! let mut block = IndexedBlock::new();
+//! block.tag().set_index(5);
+//! block.data_mut()[0] = 42;
+//! cipher.encrypt_block(&mut block);
+//! assert_ne!(block.data()[0], 42);
+//! cipher.decrypt_block(&mut block);
+//! assert_eq!(block.data()[0], 42);
+//! ```
+//!
+//! The substitution uses table lookups, so like [`alg2`](crate::alg2) it is not constant time
+
+use crate::alg2::S_BOX;
+use crate::splitmix::SplitMix64;
+use crate::{GenericIndexedBlock, Tag, Word, WordCipher};
+
+const ROUNDS: usize = 4;
+
+/// The inverse of [`S_BOX`]
+const INV_S_BOX: [u8; 256] = invert(&S_BOX);
+
+const fn invert(sbox: &[u8; 256]) -> [u8; 256] {
+    let mut inverse = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        inverse[sbox[i] as usize] = i as u8;
+        i += 1;
+    }
+    inverse
+}
+
+/// A [`WordCipher`] followed by keyed, index dependent substitution and permutation rounds
+#[derive(Clone)]
+pub struct Hardened<C> {
+    cipher: C,
+    key: u64,
+}
+
+impl<C> Hardened<C> {
+    /// Hardens `cipher` with a secret `key`. The key should be random and independent of the
+    /// block key, index key and any header key
+    pub const fn new(cipher: C, key: u64) -> Self {
+        Self { cipher, key }
+    }
+
+    /// Returns the wrapped cipher
+    pub fn cipher(&self) -> &C {
+        &self.cipher
+    }
+
+    /// Encrypts `words` in place using `index`
+    pub fn encrypt_words<W: Word, const N: usize>(&self, index: C::Index, words: &mut [W; N])
+    where
+        C: WordCipher<W, N>,
+        C::Index: Into<u64>,
+    {
+        self.cipher.cipher_words(index, words);
+        for seed in self.round_seeds(index.into()) {
+            substitute(words, seed);
+            permute(words, seed);
+        }
+    }
+
+    /// Decrypts `words` that were encrypted by [`Hardened::encrypt_words`] with the same `index`
+    pub fn decrypt_words<W: Word, const N: usize>(&self, index: C::Index, words: &mut [W; N])
+    where
+        C: WordCipher<W, N>,
+        C::Index: Into<u64>,
+    {
+        for seed in self.round_seeds(index.into()).into_iter().rev() {
+            unpermute(words, seed);
+            unsubstitute(words, seed);
+        }
+        self.cipher.cipher_words(index, words);
+    }
+
+    /// Encrypts `block` in place, using the index stored in its tag
+    pub fn encrypt_block<T, W: Word, const N: usize, A>(
+        &self,
+        block: &mut GenericIndexedBlock<T, W, N, A>,
+    ) where
+        T: Tag,
+        C: WordCipher<W, N, Index = T::IndexTy>,
+        C::Index: Into<u64>,
+    {
+        let index = block.index();
+        self.encrypt_words(index, block.data_mut());
+    }
+
+    /// Decrypts `block` in place, using the index stored in its tag
+    pub fn decrypt_block<T, W: Word, const N: usize, A>(
+        &self,
+        block: &mut GenericIndexedBlock<T, W, N, A>,
+    ) where
+        T: Tag,
+        C: WordCipher<W, N, Index = T::IndexTy>,
+        C::Index: Into<u64>,
+    {
+        let index = block.index();
+        self.decrypt_words(index, block.data_mut());
+    }
+
+    /// Returns the seed of each round for the block at `index`. The index is mixed before it is
+    /// combined with the key, so that nearby indices start from unrelated states
+    fn round_seeds(&self, index: u64) -> [u64; ROUNDS] {
+        let mut state = SplitMix64(self.key ^ SplitMix64(index).next());
+        [(); ROUNDS].map(|()| state.next())
+    }
+}
+
+/// Calls `f` with each byte of `words` and its key byte. Key bytes are assigned in little endian
+/// order within each word, so the result does not depend on the byte order of the target
+fn for_each_byte<W: Word, const N: usize>(
+    words: &mut [W; N],
+    seed: u64,
+    mut f: impl FnMut(&mut u8, u8),
+) {
+    let size = core::mem::size_of::<W>();
+    let bytes = crate::pod::bytes_of_mut(words);
+    let mut keys = SplitMix64(seed);
+    let mut key = 0u64;
+    for logical in 0..bytes.len() {
+        if logical % 8 == 0 {
+            key = keys.next();
+        }
+        let native = if cfg!(target_endian = "little") {
+            logical
+        } else {
+            logical - logical % size + size - 1 - logical % size
+        };
+        f(&mut bytes[native], (key >> (logical % 8 * 8)) as u8);
+    }
+}
+
+fn substitute<W: Word, const N: usize>(words: &mut [W; N], seed: u64) {
+    for_each_byte(words, seed, |byte, key| {
+        *byte = S_BOX[(*byte ^ key) as usize]
+    });
+}
+
+fn unsubstitute<W: Word, const N: usize>(words: &mut [W; N], seed: u64) {
+    for_each_byte(words, seed, |byte, key| {
+        *byte = INV_S_BOX[*byte as usize] ^ key
+    });
+}
+
+/// Returns the word permutation for `seed`, using a Fisher-Yates shuffle. The seed is rotated so
+/// that the shuffle does not reuse the first key bytes of the substitution
+fn permutation<const N: usize>(seed: u64) -> [usize; N] {
+    let mut order = [0usize; N];
+    for (i, slot) in order.iter_mut().enumerate() {
+        *slot = i;
+    }
+    let mut state = SplitMix64(seed.rotate_left(32));
+    for i in (1..N).rev() {
+        let j = (state.next() % (i as u64 + 1)) as usize;
+        order.swap(i, j);
+    }
+    order
+}
+
+fn permute<W: Word, const N: usize>(words: &mut [W; N], seed: u64) {
+    let input = *words;
+    for (word, from) in words.iter_mut().zip(permutation::<N>(seed)) {
+        *word = input[from];
+    }
+}
+
+fn unpermute<W: Word, const N: usize>(words: &mut [W; N], seed: u64) {
+    let input = *words;
+    for (word, from) in input.iter().zip(permutation::<N>(seed)) {
+        words[from] = *word;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alg1, alg2, alg3, Key, KEY};
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn inverse_s_box() {
+        for b in 0..=255u8 {
+            assert_eq!(INV_S_BOX[S_BOX[b as usize] as usize], b);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+
+        let cipher = Hardened::new(alg1::Algorithm1::new(&KEY, rng.gen()), rng.gen());
+        for index in 0..100u32 {
+            let mut block = alg1::IndexedBlock::new();
+            block.tag().set_index(index);
+            rng.fill(block.data_mut());
+            let plaintext = *block.data();
+            cipher.encrypt_block(&mut block);
+            assert_ne!(block.data(), &plaintext);
+            cipher.decrypt_block(&mut block);
+            assert_eq!(block.data(), &plaintext);
+        }
+
+        let cipher = Hardened::new(alg2::Algorithm2::new(&KEY, rng.gen()), rng.gen());
+        let mut block = alg2::IndexedBlock::new();
+        block.tag().set_index(0x00AB_CDEF_0123_4567);
+        rng.fill(block.data_mut());
+        let plaintext = *block.data();
+        cipher.encrypt_block(&mut block);
+        cipher.decrypt_block(&mut block);
+        assert_eq!(block.data(), &plaintext);
+
+        let cipher = Hardened::new(alg3::Algorithm3::new(&KEY, rng.gen()), rng.gen());
+        let mut words: [u64; 7] = rng.gen();
+        let plaintext = words;
+        cipher.encrypt_words(99, &mut words);
+        cipher.decrypt_words(99, &mut words);
+        assert_eq!(words, plaintext);
+    }
+
+    #[test]
+    fn known_plaintext_does_not_reveal_pad() {
+        let key = Key::new([0x5Au8; 64]);
+        let plain = alg1::Algorithm1::new(&key, 0);
+        let cipher = Hardened::new(alg1::Algorithm1::new(&key, 0), 1);
+
+        // Xoring plaintext with ciphertext recovers the pad of the plain cipher, but not here
+        let mut pad = [0u32; 7];
+        plain.cipher_words(3, &mut pad);
+        let mut words = [0u32; 7];
+        cipher.encrypt_words(3, &mut words);
+        assert_ne!(words, pad);
+
+        // Index 4 and a different hardening key give unrelated ciphertexts
+        let mut other = [0u32; 7];
+        cipher.encrypt_words(4, &mut other);
+        assert_ne!(other, words);
+        let mut other = [0u32; 7];
+        Hardened::new(alg1::Algorithm1::new(&key, 0), 2).encrypt_words(3, &mut other);
+        assert_ne!(other, words);
+    }
+
+    #[test]
+    fn wrong_key() {
+        let cipher = Hardened::new(alg1::Algorithm1::new(&KEY, 0), 1);
+        let wrong = Hardened::new(alg1::Algorithm1::new(&KEY, 0), 2);
+        let mut words = [1u32, 2, 3, 4, 5, 6, 7];
+        cipher.encrypt_words(10, &mut words);
+        wrong.decrypt_words(10, &mut words);
+        assert_ne!(words, [1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn permutations() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let mut order = permutation::<31>(rng.gen());
+            order.sort_unstable();
+            assert!(order.iter().enumerate().all(|(i, &j)| i == j));
+        }
+    }
+}
@@ -0,0 +1,433 @@
+//! A self describing header for messages, naming the algorithm, word mode, key and starting index
+//! used to encrypt them.
+//!
+//! The output of every algorithm looks like random bytes, so without a header the receiver must
+//! know out of band how a message was encrypted. A message is a [`Header`] followed by any number
+//! of blocks of [`DynBlockCipher::block_size`] bytes, encrypted with consecutive indices starting
+//! at [`Header::start_index`]. [`cipher_message`] parses the header and dispatches to the matching
+//! cipher:
+//!
+//! ```
+//! use encryption::{cipher_message, AlgorithmId, DynBlockCipher, Header, Key, HEADER_SIZE};
+//! use encryption::{alg1::Algorithm1, alg2::Algorithm2};
+//!
+//! let key = Key::new([7u8; 512]);
+//! let alg1 = Algorithm1::new(&key, 0x1234);
+//! let alg2 = Algorithm2::new(&key, 0x1234);
+//! let ciphers: [(u8, &dyn DynBlockCipher); 2] = [(0, &alg1), (0, &alg2)];
+//!
+//! let mut message = vec![0u8; HEADER_SIZE + 2 * 248];
+//! message[..HEADER_SIZE].copy_from_slice(&Header::new(AlgorithmId::Alg2, 0, 100).to_bytes());
+//! message[HEADER_SIZE..].fill(0xAB);
+//! cipher_message(&ciphers, &mut message).unwrap();
+//!
+//! // The receiver learns everything it needs from the header
+//! let header = cipher_message(&ciphers, &mut message).unwrap();
+//! assert_eq!(header.algorithm, AlgorithmId::Alg2);
+//! assert!(message[HEADER_SIZE..].iter().all(|&b| b == 0xAB));
+//! ```
+
+use core::fmt;
+
+use crate::wire::check_length;
+use crate::{CipherError, DynBlockCipher, WireError, WordMode};
+
+/// The version of the header layout written by [`Header::to_bytes`]
+pub const HEADER_VERSION: u8 = 1;
+
+/// The number of bytes in a header: the version, algorithm, mode and key ID bytes, followed by the
+/// starting index in little endian byte order
+pub const HEADER_SIZE: usize = 12;
+
+/// Identifies an algorithm, and with it the block size and index hash used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum AlgorithmId {
+    /// [`alg1`](crate::alg1), 28 byte blocks and the identity hash
+    Alg1 = 1,
+    /// [`alg2`](crate::alg2), 248 byte blocks hashed through the AES S-BOX
+    Alg2 = 2,
+    /// [`alg3`](crate::alg3), 56 byte blocks hashed through the AES S-BOX
+    Alg3 = 3,
+    /// [`chacha`](crate::chacha), 28 byte blocks of the ChaCha20 keystream
+    ChaCha20 = 4,
+}
+
+impl AlgorithmId {
+    /// Returns the algorithm with the ID `id`, or None if it is unknown
+    pub fn from_u8(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Self::Alg1),
+            2 => Some(Self::Alg2),
+            3 => Some(Self::Alg3),
+            4 => Some(Self::ChaCha20),
+            _ => None,
+        }
+    }
+
+    /// A short name for the algorithm, for configuration and logging
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Alg1 => "alg1",
+            Self::Alg2 => "alg2",
+            Self::Alg3 => "alg3",
+            Self::ChaCha20 => "chacha20",
+        }
+    }
+
+    /// Returns true if the output of this algorithm depends on the [`WordMode`]
+    pub const fn uses_word_mode(self) -> bool {
+        !matches!(self, Self::ChaCha20)
+    }
+}
+
+/// Describes how the blocks following it were encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub algorithm: AlgorithmId,
+    pub mode: WordMode,
+    /// Selects between ciphers of the same algorithm, as during key rotation
+    pub key_id: u8,
+    /// The index of the first block. Each following block uses the next index
+    pub start_index: u64,
+}
+
+impl Header {
+    /// Creates a header for blocks encrypted with the [`WordMode`] this crate was compiled with
+    pub fn new(algorithm: AlgorithmId, key_id: u8, start_index: u64) -> Self {
+        Self {
+            algorithm,
+            mode: WordMode::COMPILED,
+            key_id,
+            start_index,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0] = HEADER_VERSION;
+        bytes[1] = self.algorithm as u8;
+        bytes[2] = self.mode as u8;
+        bytes[3] = self.key_id;
+        bytes[4..].copy_from_slice(&self.start_index.to_le_bytes());
+        bytes
+    }
+
+    /// Parses a header from exactly [`HEADER_SIZE`] bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HeaderError> {
+        check_length(bytes, HEADER_SIZE)?;
+        if bytes[0] != HEADER_VERSION {
+            return Err(HeaderError::UnsupportedVersion(bytes[0]));
+        }
+        Ok(Self {
+            algorithm: AlgorithmId::from_u8(bytes[1])
+                .ok_or(HeaderError::UnknownAlgorithm(bytes[1]))?,
+            mode: WordMode::from_u8(bytes[2]).ok_or(HeaderError::UnknownMode(bytes[2]))?,
+            key_id: bytes[3],
+            start_index: u64::from_le_bytes(bytes[4..].try_into().unwrap()),
+        })
+    }
+
+    /// Creates a header for blocks encrypted by `cipher`, using its algorithm and word mode
+    pub fn for_cipher(cipher: &dyn DynBlockCipher, key_id: u8, start_index: u64) -> Self {
+        Self {
+            algorithm: cipher.algorithm(),
+            mode: cipher.word_mode().unwrap_or(WordMode::COMPILED),
+            key_id,
+            start_index,
+        }
+    }
+
+    /// Returns the cipher in `ciphers` for the algorithm, word mode and key ID of this header.
+    /// Entries pair a key ID with a cipher.
+    ///
+    /// Returns Err if no cipher matches
+    pub fn select<'c>(
+        &self,
+        ciphers: &[(u8, &'c dyn DynBlockCipher)],
+    ) -> Result<&'c dyn DynBlockCipher, HeaderError> {
+        let mut candidates = ciphers
+            .iter()
+            .filter(|(key_id, cipher)| {
+                *key_id == self.key_id && cipher.algorithm() == self.algorithm
+            })
+            .map(|(_, cipher)| *cipher)
+            .peekable();
+        if candidates.peek().is_none() {
+            return Err(HeaderError::NoCipher {
+                algorithm: self.algorithm,
+                key_id: self.key_id,
+            });
+        }
+        candidates
+            .find(|cipher| {
+                !self.algorithm.uses_word_mode() || cipher.word_mode() == Some(self.mode)
+            })
+            .ok_or(HeaderError::UnsupportedMode(self.mode))
+    }
+}
+
+/// Encrypts or decrypts the blocks of `message` in place, using the cipher in `ciphers` selected
+/// by the header at the start of `message`. Entries in `ciphers` pair a key ID with a cipher.
+///
+/// Returns the parsed header. Returns Err and leaves `message` unchanged if the header is invalid,
+/// no cipher matches, the blocks do not evenly fill the rest of the message, or the index of a
+/// block does not fit in the index type of the algorithm
+pub fn cipher_message(
+    ciphers: &[(u8, &dyn DynBlockCipher)],
+    message: &mut [u8],
+) -> Result<Header, HeaderError> {
+    if message.len() < HEADER_SIZE {
+        return Err(WireError::WrongLength {
+            expected: HEADER_SIZE,
+            actual: message.len(),
+        }
+        .into());
+    }
+    let (header, body) = message.split_at_mut(HEADER_SIZE);
+    let header = Header::from_bytes(header)?;
+    let cipher = header.select(ciphers)?;
+
+    let block_size = cipher.block_size();
+    if body.len() % block_size != 0 {
+        return Err(HeaderError::PartialBlock {
+            block_size,
+            actual: body.len(),
+        });
+    }
+    // Check the last index before changing any block
+    let blocks = (body.len() / block_size) as u64;
+    if blocks > 0 {
+        let max = u64::MAX >> (64 - cipher.index_bits());
+        let last = header.start_index.checked_add(blocks - 1);
+        if !matches!(last, Some(last) if last <= max) {
+            return Err(CipherError::IndexOutOfRange {
+                index: last.unwrap_or(u64::MAX),
+            }
+            .into());
+        }
+    }
+
+    for (index, block) in (header.start_index..).zip(body.chunks_exact_mut(block_size)) {
+        cipher.cipher_bytes(index, block)?;
+    }
+    Ok(header)
+}
+
+/// Errors returned when parsing a [`Header`] or dispatching a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderError {
+    Wire(WireError),
+    Cipher(CipherError),
+    /// The header was written by an incompatible version of this crate
+    UnsupportedVersion(u8),
+    UnknownAlgorithm(u8),
+    UnknownMode(u8),
+    /// A cipher was provided for the algorithm and key ID in the header, but not in the word mode
+    /// the blocks were encrypted with
+    UnsupportedMode(WordMode),
+    /// No cipher was provided for the algorithm and key ID in the header
+    NoCipher {
+        algorithm: AlgorithmId,
+        key_id: u8,
+    },
+    /// The message after the header is not a whole number of blocks
+    PartialBlock {
+        block_size: usize,
+        actual: usize,
+    },
+}
+
+impl From<WireError> for HeaderError {
+    fn from(e: WireError) -> Self {
+        HeaderError::Wire(e)
+    }
+}
+
+impl From<CipherError> for HeaderError {
+    fn from(e: CipherError) -> Self {
+        HeaderError::Cipher(e)
+    }
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::Wire(e) => write!(f, "{}", e),
+            HeaderError::Cipher(e) => write!(f, "{}", e),
+            HeaderError::UnsupportedVersion(version) => {
+                write!(f, "unsupported header version {}", version)
+            }
+            HeaderError::UnknownAlgorithm(id) => write!(f, "unknown algorithm ID {}", id),
+            HeaderError::UnknownMode(id) => write!(f, "unknown word mode ID {}", id),
+            HeaderError::UnsupportedMode(mode) => {
+                write!(f, "no cipher for blocks encrypted in {:?} mode", mode)
+            }
+            HeaderError::NoCipher { algorithm, key_id } => {
+                write!(f, "no {} cipher with key ID {}", algorithm.name(), key_id)
+            }
+            HeaderError::PartialBlock { block_size, actual } => write!(
+                f,
+                "{} bytes after the header is not a multiple of the {} byte block size",
+                actual, block_size
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HeaderError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alg1, alg2, alg3, chacha, Key};
+    use std::vec;
+
+    #[test]
+    fn header_bytes() {
+        let header = Header::new(AlgorithmId::Alg3, 9, 0x0102_0304_0506_0708);
+        let bytes = header.to_bytes();
+        assert_eq!(
+            bytes,
+            [1, 3, WordMode::COMPILED as u8, 9, 8, 7, 6, 5, 4, 3, 2, 1]
+        );
+        assert_eq!(Header::from_bytes(&bytes), Ok(header));
+
+        let mut bad = bytes;
+        bad[0] = 2;
+        assert_eq!(
+            Header::from_bytes(&bad),
+            Err(HeaderError::UnsupportedVersion(2))
+        );
+        let mut bad = bytes;
+        bad[1] = 0;
+        assert_eq!(
+            Header::from_bytes(&bad),
+            Err(HeaderError::UnknownAlgorithm(0))
+        );
+        let mut bad = bytes;
+        bad[2] = 7;
+        assert_eq!(Header::from_bytes(&bad), Err(HeaderError::UnknownMode(7)));
+        assert_eq!(
+            Header::from_bytes(&bytes[..11]),
+            Err(HeaderError::Wire(WireError::WrongLength {
+                expected: 12,
+                actual: 11
+            }))
+        );
+    }
+
+    #[test]
+    fn dispatch() {
+        let key = Key::new([0x42u8; 512]);
+        let other_key = Key::new([0x24u8; 512]);
+        let alg1 = alg1::Algorithm1::new(&key, 1);
+        let alg1_rotated = alg1::Algorithm1::new(&other_key, 1);
+        let alg2 = alg2::Algorithm2::new(&key, 2);
+        let alg3 = alg3::Algorithm3::new(&key, 3);
+        let chacha = chacha::ChaCha20::new(&key);
+        let ciphers: [(u8, &dyn DynBlockCipher); 5] = [
+            (0, &alg1),
+            (1, &alg1_rotated),
+            (0, &alg2),
+            (0, &alg3),
+            (0, &chacha),
+        ];
+
+        for (algorithm, key_id, block_size) in [
+            (AlgorithmId::Alg1, 0, 28),
+            (AlgorithmId::Alg1, 1, 28),
+            (AlgorithmId::Alg2, 0, 248),
+            (AlgorithmId::Alg3, 0, 56),
+            (AlgorithmId::ChaCha20, 0, 28),
+        ] {
+            let header = Header::new(algorithm, key_id, 5);
+            let mut message = vec![0x11u8; HEADER_SIZE + 3 * block_size];
+            message[..HEADER_SIZE].copy_from_slice(&header.to_bytes());
+
+            assert_eq!(cipher_message(&ciphers, &mut message), Ok(header));
+            // Must match encrypting each block with the selected cipher directly
+            let cipher = header.select(&ciphers).unwrap();
+            let mut expected = vec![0x11u8; block_size];
+            cipher.cipher_bytes(7, &mut expected).unwrap();
+            assert_eq!(message[HEADER_SIZE + 2 * block_size..], expected);
+
+            cipher_message(&ciphers, &mut message).unwrap();
+            assert!(message[HEADER_SIZE..].iter().all(|&b| b == 0x11));
+        }
+
+        // Key IDs select between ciphers of the same algorithm
+        let selected = Header::new(AlgorithmId::Alg1, 1, 0)
+            .select(&ciphers)
+            .unwrap();
+        let (mut a, mut b) = ([0u8; 28], [0u8; 28]);
+        selected.cipher_bytes(0, &mut a).unwrap();
+        alg1_rotated.cipher_bytes(0, &mut b).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn dispatch_errors() {
+        let key = Key::new([0x42u8; 512]);
+        let alg1 = alg1::Algorithm1::new(&key, 1);
+        let ciphers: [(u8, &dyn DynBlockCipher); 1] = [(0, &alg1)];
+
+        let mut message = [0u8; HEADER_SIZE + 28];
+        message[..HEADER_SIZE].copy_from_slice(&Header::new(AlgorithmId::Alg2, 0, 0).to_bytes());
+        assert_eq!(
+            cipher_message(&ciphers, &mut message),
+            Err(HeaderError::NoCipher {
+                algorithm: AlgorithmId::Alg2,
+                key_id: 0
+            })
+        );
+
+        message[..HEADER_SIZE].copy_from_slice(&Header::new(AlgorithmId::Alg1, 3, 0).to_bytes());
+        assert!(matches!(
+            cipher_message(&ciphers, &mut message),
+            Err(HeaderError::NoCipher { key_id: 3, .. })
+        ));
+
+        let mut header = Header::new(AlgorithmId::Alg1, 0, 0);
+        header.mode = match WordMode::COMPILED {
+            WordMode::BitOffset => WordMode::WordXor,
+            WordMode::WordXor => WordMode::BitOffset,
+        };
+        message[..HEADER_SIZE].copy_from_slice(&header.to_bytes());
+        assert_eq!(
+            cipher_message(&ciphers, &mut message),
+            Err(HeaderError::UnsupportedMode(header.mode))
+        );
+        // Adding a cipher in the other mode makes the message readable
+        let other_mode = alg1::Algorithm1::new(&key, 1).with_mode(header.mode);
+        let both: [(u8, &dyn DynBlockCipher); 2] = [(0, &alg1), (0, &other_mode)];
+        assert_eq!(cipher_message(&both, &mut message), Ok(header));
+        assert_eq!(Header::for_cipher(&other_mode, 0, 0), header);
+
+        message[..HEADER_SIZE].copy_from_slice(&Header::new(AlgorithmId::Alg1, 0, 0).to_bytes());
+        assert_eq!(
+            cipher_message(&ciphers, &mut message[..HEADER_SIZE + 27]),
+            Err(HeaderError::PartialBlock {
+                block_size: 28,
+                actual: 27
+            })
+        );
+        assert!(matches!(
+            cipher_message(&ciphers, &mut message[..4]),
+            Err(HeaderError::Wire(_))
+        ));
+
+        // Alg1 has 32 bit indices, so a message may not run past index u32::MAX
+        let mut message = [0u8; HEADER_SIZE + 2 * 28];
+        let header = Header::new(AlgorithmId::Alg1, 0, u32::MAX as u64);
+        message[..HEADER_SIZE].copy_from_slice(&header.to_bytes());
+        assert_eq!(
+            cipher_message(&ciphers, &mut message),
+            Err(HeaderError::Cipher(CipherError::IndexOutOfRange {
+                index: 1 << 32
+            }))
+        );
+        assert!(message[HEADER_SIZE..].iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,324 @@
+//! Known answer tests, for checking at startup that the algorithms still produce the committed
+//! outputs.
+//!
+//! The vectors in `src/kat/vectors.rs` cover [`Algorithm1`] and [`Algorithm2`] in both
+//! [`WordMode`]s, [`Tag31_1`] packing and [`alg2::hash`]. Everything is derived from [`SEED`]: the
+//! key from the first [`KEY_SIZE`] bytes of a SplitMix64 stream, and the inputs of each vector from
+//! the words that follow. Every block encrypts [`plaintext`]. Keys are read as little endian
+//! words on every target, so the same vectors hold on big endian firmware.
+//!
+//! [`self_test`] only uses `core`, so it can run as a power-on self test in `no_std` firmware:
+//!
+//! ```
+//! encryption::kat::self_test().expect("Known answer test failed");
+//! ```
+//!
+//! After an intentional change to an algorithm, regenerate the vectors with
+//! `cargo test --lib kat::tests::generate_vectors -- --ignored`
+
+use core::fmt;
+
+use crate::alg1::{self, Algorithm1};
+use crate::alg2::{self, Algorithm2};
+use crate::splitmix::SplitMix64;
+use crate::{Key, Tag, Tag31_1, WordMode};
+
+#[rustfmt::skip]
+mod vectors;
+pub use vectors::{ALG1, ALG2, HASH, TAG31_1};
+
+/// The seed that the key and every input are generated from
+pub const SEED: u64 = 0x4B41_545F_5345_4544;
+
+/// The number of bytes in [`KEY`]
+pub const KEY_SIZE: usize = 512;
+
+/// The key used by every cipher vector
+pub static KEY: Key<KEY_SIZE> = Key::new(key_bytes());
+
+const fn key_bytes() -> [u8; KEY_SIZE] {
+    let mut bytes = [0u8; KEY_SIZE];
+    let mut state = SplitMix64(SEED);
+    let mut i = 0;
+    while i < KEY_SIZE {
+        let word = state.next().to_le_bytes();
+        let mut j = 0;
+        while j < 8 {
+            bytes[i + j] = word[j];
+            j += 1;
+        }
+        i += 8;
+    }
+    bytes
+}
+
+/// Returns the plaintext encrypted by every cipher vector. Every byte is different from its
+/// neighbours, so a keystream that is shifted or Ored instead of Xored changes the output
+pub fn plaintext<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (i as u8).wrapping_mul(37).wrapping_add(11);
+    }
+    bytes
+}
+
+/// The expected output of encrypting [`plaintext`] with [`KEY`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CipherVector<I, const N: usize> {
+    pub mode: WordMode,
+    pub index_key: I,
+    pub index: I,
+    pub ciphertext: [u8; N],
+}
+
+/// The raw value of a [`Tag31_1`] holding `index` and `tag`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TagVector {
+    pub index: u32,
+    pub tag: usize,
+    pub raw: u32,
+}
+
+/// The output of [`alg2::hash`] for `input`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashVector {
+    pub input: u64,
+    pub output: u64,
+}
+
+/// Identifies the first vector that did not match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestError {
+    Algorithm1 { vector: usize },
+    Algorithm2 { vector: usize },
+    Tag31_1 { vector: usize },
+    Hash { vector: usize },
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, vector) = match self {
+            SelfTestError::Algorithm1 { vector } => ("Algorithm1", vector),
+            SelfTestError::Algorithm2 { vector } => ("Algorithm2", vector),
+            SelfTestError::Tag31_1 { vector } => ("Tag31_1", vector),
+            SelfTestError::Hash { vector } => ("alg2::hash", vector),
+        };
+        write!(f, "{} known answer test {} failed", name, vector)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SelfTestError {}
+
+/// Checks every known answer vector, including that each ciphertext decrypts to [`plaintext`].
+/// Returns the first vector that does not match
+pub fn self_test() -> Result<(), SelfTestError> {
+    check(&ALG1, &ALG2, &TAG31_1, &HASH)
+}
+
+fn check(
+    alg1_vectors: &[CipherVector<u32, 28>],
+    alg2_vectors: &[CipherVector<u64, 248>],
+    tag_vectors: &[TagVector],
+    hash_vectors: &[HashVector],
+) -> Result<(), SelfTestError> {
+    for (vector, v) in alg1_vectors.iter().enumerate() {
+        let cipher = Algorithm1::new(&KEY, v.index_key).with_mode(v.mode);
+        let mut block = alg1::CipherBlock::new(plaintext());
+        cipher.cipher_block(v.index, &mut block);
+        let encrypted = block.0 == v.ciphertext;
+        cipher.cipher_block(v.index, &mut block);
+        if !encrypted || block.0 != plaintext() {
+            return Err(SelfTestError::Algorithm1 { vector });
+        }
+    }
+
+    for (vector, v) in alg2_vectors.iter().enumerate() {
+        let cipher = Algorithm2::new(&KEY, v.index_key).with_mode(v.mode);
+        let mut block = alg2::CipherBlock::new(plaintext());
+        cipher.cipher_block(v.index, &mut block);
+        let encrypted = block.0 == v.ciphertext;
+        cipher.cipher_block(v.index, &mut block);
+        if !encrypted || block.0 != plaintext() {
+            return Err(SelfTestError::Algorithm2 { vector });
+        }
+    }
+
+    for (vector, v) in tag_vectors.iter().enumerate() {
+        let mut tag = Tag31_1::new(v.index);
+        tag.set_tag(v.tag);
+        let unpacked = Tag31_1::from_raw(v.raw);
+        if tag.to_raw() != v.raw || unpacked.get_index() != v.index || unpacked.get_tag() != v.tag {
+            return Err(SelfTestError::Tag31_1 { vector });
+        }
+    }
+
+    for (vector, v) in hash_vectors.iter().enumerate() {
+        if alg2::hash(v.input) != v.output {
+            return Err(SelfTestError::Hash { vector });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+    use std::format;
+    use std::string::String;
+    use std::vec::Vec;
+
+    const MODES: [WordMode; 2] = [WordMode::BitOffset, WordMode::WordXor];
+
+    fn bytes(out: &mut String, bytes: &[u8]) {
+        out.push_str("[\n");
+        for line in bytes.chunks(16) {
+            out.push_str("        ");
+            let line: Vec<_> = line.iter().map(|b| format!("0x{:02x},", b)).collect();
+            writeln!(out, "{}", line.join(" ")).unwrap();
+        }
+        out.push_str("    ]");
+    }
+
+    /// Returns the contents of `src/kat/vectors.rs`, computed by the current implementation
+    fn generate() -> String {
+        let mut state = SplitMix64(SEED);
+        let key: Vec<u8> = (0..KEY_SIZE / 8)
+            .flat_map(|_| state.next().to_le_bytes())
+            .collect();
+        assert_eq!(key, KEY.as_bytes());
+
+        let mut out = String::new();
+        out.push_str(
+            "//! Known answer vectors, generated by `kat::tests::generate_vectors` from `SEED`. \
+             Do not edit\n\nuse super::{CipherVector, HashVector, TagVector};\n\
+             use crate::WordMode;\n",
+        );
+
+        writeln!(out, "\npub const ALG1: [CipherVector<u32, 28>; 8] = [").unwrap();
+        for mode in MODES {
+            for _ in 0..4 {
+                let (index_key, index) = (state.next() as u32, state.next() as u32);
+                let mut block = alg1::CipherBlock::new(plaintext());
+                Algorithm1::new(&KEY, index_key)
+                    .with_mode(mode)
+                    .cipher_block(index, &mut block);
+                write!(
+                    out,
+                    "    CipherVector {{ mode: WordMode::{:?}, index_key: 0x{:08x}, index: 0x{:08x}, \
+                     ciphertext: ",
+                    mode, index_key, index
+                )
+                .unwrap();
+                bytes(&mut out, &block.0);
+                out.push_str(" },\n");
+            }
+        }
+        out.push_str("];\n");
+
+        writeln!(out, "\npub const ALG2: [CipherVector<u64, 248>; 4] = [").unwrap();
+        for mode in MODES {
+            for _ in 0..2 {
+                let (index_key, index) = (state.next(), state.next());
+                let mut block = alg2::CipherBlock::new(plaintext());
+                Algorithm2::new(&KEY, index_key)
+                    .with_mode(mode)
+                    .cipher_block(index, &mut block);
+                write!(
+                    out,
+                    "    CipherVector {{ mode: WordMode::{:?}, index_key: 0x{:016x}, index: 0x{:016x}, \
+                     ciphertext: ",
+                    mode, index_key, index
+                )
+                .unwrap();
+                bytes(&mut out, &block.0);
+                out.push_str(" },\n");
+            }
+        }
+        out.push_str("];\n");
+
+        writeln!(out, "\npub const TAG31_1: [TagVector; 8] = [").unwrap();
+        for _ in 0..8 {
+            let word = state.next();
+            let (index, tag) = (word as u32 & Tag31_1::INDEX_MASK, (word >> 32) as usize & 1);
+            let mut packed = Tag31_1::new(index);
+            packed.set_tag(tag);
+            writeln!(
+                out,
+                "    TagVector {{ index: 0x{:08x}, tag: {}, raw: 0x{:08x} }},",
+                index,
+                tag,
+                packed.to_raw()
+            )
+            .unwrap();
+        }
+        out.push_str("];\n");
+
+        writeln!(out, "\npub const HASH: [HashVector; 8] = [").unwrap();
+        for _ in 0..8 {
+            let input = state.next();
+            writeln!(
+                out,
+                "    HashVector {{ input: 0x{:016x}, output: 0x{:016x} }},",
+                input,
+                alg2::hash(input)
+            )
+            .unwrap();
+        }
+        out.push_str("];\n");
+        out
+    }
+
+    #[test]
+    fn self_test_passes() {
+        assert_eq!(self_test(), Ok(()));
+    }
+
+    #[test]
+    fn vectors_are_current() {
+        assert!(
+            generate() == include_str!("kat/vectors.rs"),
+            "src/kat/vectors.rs is out of date. If the change was intentional, regenerate it with \
+             `cargo test --lib kat::tests::generate_vectors -- --ignored`"
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn generate_vectors() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/kat/vectors.rs");
+        std::fs::write(path, generate()).unwrap();
+    }
+
+    #[test]
+    fn detects_mismatches() {
+        let mut alg1_vectors = ALG1;
+        alg1_vectors[5].ciphertext[27] ^= 1;
+        assert_eq!(
+            check(&alg1_vectors, &ALG2, &TAG31_1, &HASH),
+            Err(SelfTestError::Algorithm1 { vector: 5 })
+        );
+
+        let mut alg2_vectors = ALG2;
+        alg2_vectors[1].mode = WordMode::WordXor;
+        assert_eq!(
+            check(&ALG1, &alg2_vectors, &TAG31_1, &HASH),
+            Err(SelfTestError::Algorithm2 { vector: 1 })
+        );
+
+        let mut tag_vectors = TAG31_1;
+        tag_vectors[2].tag ^= 1;
+        assert_eq!(
+            check(&ALG1, &ALG2, &tag_vectors, &HASH),
+            Err(SelfTestError::Tag31_1 { vector: 2 })
+        );
+
+        let mut hash_vectors = HASH;
+        hash_vectors[7].output ^= 1 << 63;
+        assert_eq!(
+            check(&ALG1, &ALG2, &TAG31_1, &hash_vectors),
+            Err(SelfTestError::Hash { vector: 7 })
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Known answer vectors, generated by `kat::tests::generate_vectors` from `SEED`. Do not edit
+
+use super::{CipherVector, HashVector, TagVector};
+use crate::WordMode;
+
+pub const ALG1: [CipherVector<u32, 28>; 8] = [
+    CipherVector { mode: WordMode::BitOffset, index_key: 0x647902c0, index: 0x5df937b3, ciphertext: [
+        0x39, 0x3b, 0xa5, 0x58, 0x25, 0xc6, 0x19, 0x2c, 0xba, 0x50, 0x45, 0x04, 0x74, 0xfa, 0x01, 0x81,
+        0x2e, 0x98, 0xa5, 0x98, 0x0f, 0x16, 0x29, 0xd5, 0xe8, 0xac, 0xad, 0x86,
+    ] },
+    CipherVector { mode: WordMode::BitOffset, index_key: 0xb9c12749, index: 0x53b1fc54, ciphertext: [
+        0x09, 0x30, 0x55, 0x7a, 0x9e, 0xc4, 0xe9, 0x0e, 0x34, 0x58, 0x7d, 0xa2, 0xc2, 0xec, 0x11, 0x76,
+        0x5a, 0x80, 0xa5, 0xea, 0xeb, 0x14, 0x39, 0x1e, 0x85, 0xa8, 0xcd, 0xd2,
+    ] },
+    CipherVector { mode: WordMode::BitOffset, index_key: 0x93a0ef35, index: 0xe15aae29, ciphertext: [
+        0x05, 0x30, 0x55, 0xaa, 0x91, 0xc4, 0xe9, 0xde, 0x33, 0x58, 0x7d, 0x62, 0xc8, 0xec, 0x11, 0x66,
+        0x51, 0x80, 0xa5, 0xca, 0xe0, 0x14, 0x39, 0x8e, 0x89, 0xa8, 0xcd, 0x12,
+    ] },
+    CipherVector { mode: WordMode::BitOffset, index_key: 0x94e8a9ab, index: 0xc02a4d2f, ciphertext: [
+        0xfc, 0xff, 0x16, 0x72, 0xad, 0x7c, 0xe1, 0x02, 0xab, 0x7b, 0xb2, 0xe5, 0x9c, 0x18, 0xed, 0xc3,
+        0x89, 0xd0, 0x5b, 0x55, 0x15, 0x46, 0xc1, 0x5d, 0x96, 0xb9, 0x80, 0xdf,
+    ] },
+    CipherVector { mode: WordMode::WordXor, index_key: 0x20063635, index: 0x025f5d44, ciphertext: [
+        0x87, 0xf0, 0x0a, 0xab, 0xab, 0xd5, 0x79, 0x85, 0x43, 0x47, 0x47, 0x20, 0x05, 0x81, 0xae, 0xcc,
+        0xad, 0x27, 0xe0, 0x40, 0x88, 0xce, 0x97, 0x14, 0x3f, 0x83, 0x01, 0xf7,
+    ] },
+    CipherVector { mode: WordMode::WordXor, index_key: 0xd70b8ae1, index: 0xb0340b50, ciphertext: [
+        0xd9, 0xb0, 0xae, 0xa3, 0xd3, 0x75, 0x45, 0x14, 0x7e, 0x6b, 0x36, 0xce, 0x78, 0x00, 0x2c, 0x72,
+        0x61, 0xfb, 0x32, 0xff, 0x46, 0x42, 0x87, 0x4e, 0x24, 0x69, 0xaa, 0x84,
+    ] },
+    CipherVector { mode: WordMode::WordXor, index_key: 0xccb1df22, index: 0xd93ddcbd, ciphertext: [
+        0x41, 0x5d, 0x6c, 0xa2, 0x29, 0xa2, 0xad, 0xbe, 0xfb, 0x10, 0x1b, 0x09, 0xd9, 0x81, 0xa4, 0x5a,
+        0xb2, 0xd1, 0xbf, 0x25, 0xad, 0x37, 0xbf, 0xe4, 0xe0, 0xdf, 0xb9, 0x41,
+    ] },
+    CipherVector { mode: WordMode::WordXor, index_key: 0x2a948b9e, index: 0x081db002, ciphertext: [
+        0xe4, 0x15, 0x82, 0x4c, 0x16, 0x78, 0xc2, 0x42, 0x6f, 0x28, 0xdc, 0x86, 0x15, 0x6c, 0xea, 0xef,
+        0x17, 0x31, 0x09, 0xd0, 0xa2, 0x27, 0x72, 0x32, 0x3c, 0x44, 0xf0, 0xb6,
+    ] },
+];
+
+pub const ALG2: [CipherVector<u64, 248>; 4] = [
+    CipherVector { mode: WordMode::BitOffset, index_key: 0x40ea45920f23f0b0, index: 0xbab8c116cb0fcca2, ciphertext: [
+        0x4e, 0x91, 0x6e, 0x0f, 0x12, 0xe7, 0x3d, 0x82, 0xc1, 0x64, 0x90, 0xa3, 0x36, 0x97, 0x8d, 0xba,
+        0x7b, 0x0f, 0xf9, 0x4d, 0xd7, 0x9e, 0x43, 0xb3, 0xf6, 0x4a, 0xd7, 0xf2, 0x53, 0x02, 0x77, 0xe1,
+        0xa8, 0x0d, 0xbc, 0x2c, 0xeb, 0xc8, 0xe4, 0x1e, 0x91, 0x6c, 0x15, 0xa4, 0x44, 0xb2, 0x69, 0xac,
+        0x91, 0xd4, 0xb3, 0x37, 0xc5, 0x4f, 0xd8, 0xff, 0x11, 0xc4, 0xfd, 0xe2, 0xfb, 0x9c, 0x37, 0x6b,
+        0x0a, 0x71, 0xd2, 0x3a, 0x68, 0x9a, 0xa4, 0x80, 0x83, 0x25, 0xac, 0xa7, 0x5e, 0x1f, 0x00, 0x1f,
+        0xf9, 0x39, 0x52, 0x72, 0x13, 0x3e, 0xed, 0x5b, 0xb9, 0x42, 0x8d, 0xc6, 0x7b, 0xe4, 0x0b, 0xef,
+        0xde, 0x50, 0x86, 0x42, 0x2c, 0xb4, 0x45, 0x79, 0x84, 0x38, 0x02, 0xc6, 0xcc, 0x0b, 0x6d, 0x47,
+        0xc1, 0xd6, 0x49, 0x6b, 0x56, 0x74, 0x68, 0x36, 0x42, 0xfb, 0xac, 0xe1, 0x03, 0xc2, 0x88, 0xac,
+        0xf5, 0xac, 0x56, 0x8c, 0x2b, 0x21, 0xba, 0xcc, 0xfd, 0xd6, 0x1f, 0x9a, 0x08, 0x64, 0xa0, 0xac,
+        0x0a, 0x7c, 0x04, 0xb5, 0xc6, 0xc4, 0xb3, 0x5f, 0x18, 0xb1, 0x98, 0x5d, 0x5f, 0xb8, 0xea, 0xd5,
+        0x19, 0xb6, 0x96, 0x0f, 0x8f, 0x28, 0x8e, 0x5c, 0x56, 0xb8, 0xfa, 0x66, 0xf0, 0x5b, 0xf8, 0xaf,
+        0x4b, 0x61, 0x92, 0xe8, 0x69, 0x1f, 0x81, 0x3a, 0x4e, 0xbd, 0xc6, 0x77, 0x4c, 0x32, 0xd2, 0xc1,
+        0x51, 0xaa, 0x18, 0x84, 0xc4, 0xd3, 0x33, 0x53, 0x5c, 0xab, 0x72, 0x03, 0xb6, 0x19, 0x13, 0xb6,
+        0x1d, 0x0b, 0xbc, 0x9c, 0x45, 0xbd, 0xd4, 0x51, 0x57, 0xc4, 0x8d, 0x14, 0x38, 0xcb, 0x83, 0xd3,
+        0x64, 0xad, 0x1e, 0xb0, 0x40, 0x70, 0xdc, 0x37, 0x9c, 0xe8, 0xe4, 0x9c, 0x07, 0x85, 0x77, 0xe5,
+        0x25, 0xae, 0x4d, 0xbc, 0xea, 0xd7, 0xc0, 0x35,
+    ] },
+    CipherVector { mode: WordMode::BitOffset, index_key: 0x66344eb02eb9d75f, index: 0x888840f2a2886a62, ciphertext: [
+        0x65, 0x18, 0x7a, 0x7b, 0x1f, 0xea, 0x30, 0x72, 0x1a, 0x66, 0x93, 0xa3, 0x47, 0xc2, 0xc8, 0x4a,
+        0x17, 0xb9, 0x3b, 0xcb, 0x6f, 0xf1, 0x0f, 0xe5, 0x93, 0xc1, 0xac, 0xf2, 0x17, 0x2b, 0x0f, 0xb9,
+        0x8a, 0xdc, 0x0b, 0x1a, 0x3f, 0x8e, 0x9c, 0x4d, 0xb9, 0xa3, 0x5d, 0x42, 0xe7, 0x98, 0x4b, 0xd7,
+        0x3e, 0x18, 0xf5, 0x6b, 0x8f, 0xe8, 0x3d, 0x11, 0x2a, 0xd3, 0x88, 0x93, 0x37, 0x0e, 0x00, 0x3e,
+        0x91, 0x02, 0x25, 0xbb, 0x5f, 0x2d, 0x73, 0x27, 0xe2, 0x54, 0xeb, 0xe3, 0x07, 0x5a, 0xde, 0x8f,
+        0x38, 0xf4, 0x3b, 0x0b, 0x2f, 0x0f, 0x4a, 0xbc, 0x2d, 0x00, 0x6b, 0x33, 0x57, 0xf3, 0x17, 0x9c,
+        0xa4, 0xf3, 0xaa, 0x5b, 0x7f, 0x05, 0xd8, 0xad, 0x6a, 0x6f, 0xc5, 0x82, 0xa7, 0xc0, 0xfc, 0xed,
+        0x3a, 0x97, 0x36, 0xab, 0x4f, 0x03, 0x8b, 0x55, 0x05, 0x1e, 0x75, 0xd2, 0xf7, 0x32, 0xf9, 0x36,
+        0x7d, 0x9e, 0xbe, 0xfa, 0x1f, 0xe2, 0x31, 0xd8, 0x30, 0x17, 0x11, 0x22, 0xc7, 0x33, 0x67, 0xa8,
+        0xc7, 0xef, 0x5d, 0x4a, 0xef, 0xc0, 0x92, 0x81, 0xaa, 0x38, 0x8e, 0x72, 0x97, 0xd5, 0x76, 0x46,
+        0xee, 0xdc, 0x32, 0x9a, 0xbf, 0x39, 0xd0, 0xbf, 0x67, 0x95, 0xfb, 0xc3, 0xe7, 0x24, 0x74, 0x57,
+        0x00, 0xc7, 0xff, 0xeb, 0x0f, 0x19, 0xab, 0xe2, 0xf0, 0x78, 0x5a, 0x13, 0x37, 0x59, 0xa1, 0x9d,
+        0x34, 0x8a, 0xed, 0x3a, 0xdf, 0xb2, 0xdc, 0x38, 0xac, 0x19, 0xa3, 0x62, 0x87, 0x37, 0xd2, 0xf1,
+        0x5d, 0x0d, 0x1d, 0x8b, 0xaf, 0xb2, 0xbb, 0xe6, 0x84, 0xa6, 0x4f, 0xb3, 0xd7, 0x0c, 0x4b, 0x2a,
+        0x01, 0xf9, 0x9f, 0xdb, 0x7f, 0x92, 0x7b, 0x22, 0x2b, 0x7d, 0x32, 0x02, 0x27, 0xc6, 0x30, 0x4d,
+        0x56, 0x77, 0x8d, 0x2a, 0x4f, 0xf2, 0x69, 0x2b,
+    ] },
+    CipherVector { mode: WordMode::WordXor, index_key: 0x4186313acaa6635c, index: 0x8097bb790da7e179, ciphertext: [
+        0x6c, 0xea, 0xfb, 0x30, 0x23, 0xef, 0x25, 0x0b, 0xa5, 0x1c, 0xf5, 0x6e, 0xec, 0x8e, 0x7b, 0x8b,
+        0x06, 0x32, 0x5c, 0xe2, 0x97, 0x23, 0xad, 0xc9, 0x48, 0xc5, 0xbb, 0x9d, 0xde, 0x28, 0x7e, 0x71,
+        0x85, 0x0c, 0x8b, 0x06, 0x62, 0xc2, 0x95, 0x61, 0x07, 0xd3, 0xdb, 0x57, 0x10, 0x04, 0x05, 0xe6,
+        0xd2, 0xd4, 0x46, 0x1e, 0x02, 0xa4, 0xdf, 0x81, 0x9b, 0x80, 0xb2, 0xcb, 0x9b, 0x69, 0x2c, 0x06,
+        0xee, 0x73, 0xa5, 0xa8, 0x4c, 0x66, 0x35, 0x96, 0x20, 0x2c, 0x6f, 0x06, 0xc0, 0xa8, 0x9c, 0x84,
+        0x77, 0xde, 0x16, 0x35, 0x65, 0x39, 0x40, 0x46, 0x75, 0x8e, 0x49, 0x82, 0x9f, 0x34, 0xc7, 0x6d,
+        0xf5, 0x7d, 0x80, 0x36, 0x96, 0xf5, 0xd3, 0x01, 0x51, 0x1b, 0xdb, 0x38, 0xc4, 0xbb, 0x85, 0xa5,
+        0x23, 0x7a, 0x73, 0xd5, 0x73, 0x53, 0xe8, 0xf1, 0x8c, 0xad, 0x7a, 0xe4, 0x7e, 0xa0, 0x6a, 0x2a,
+        0xd7, 0xc0, 0x74, 0xde, 0xcd, 0xc4, 0x92, 0x57, 0xff, 0x69, 0x51, 0x38, 0x0a, 0x5f, 0xda, 0xda,
+        0x64, 0xec, 0x18, 0x0e, 0x55, 0xef, 0x2e, 0xeb, 0xaa, 0x7e, 0xf3, 0x62, 0x30, 0x7d, 0x86, 0x70,
+        0xf9, 0x7e, 0xf4, 0xf2, 0xf3, 0x6a, 0x7e, 0x12, 0xe9, 0xcb, 0xbe, 0x6b, 0x64, 0x58, 0xb9, 0x37,
+        0x2b, 0x2a, 0xc7, 0xb4, 0xef, 0x56, 0x9f, 0x5d, 0xf9, 0x2c, 0xd4, 0xe1, 0x3e, 0xc6, 0xf7, 0x15,
+        0xc1, 0xb0, 0x63, 0x12, 0xb6, 0x39, 0x9a, 0x53, 0x9e, 0xf2, 0xd1, 0x57, 0x5e, 0x85, 0x09, 0x2d,
+        0x2d, 0x47, 0x6b, 0xb0, 0x70, 0xab, 0xc4, 0x62, 0x8f, 0xec, 0x7d, 0xbf, 0x23, 0x53, 0x21, 0x09,
+        0x8b, 0x45, 0x6d, 0x24, 0x86, 0x87, 0x6f, 0xd2, 0xfe, 0xdd, 0x45, 0x74, 0xa5, 0x2f, 0x16, 0x77,
+        0xaf, 0x63, 0xb3, 0x31, 0x2e, 0xc1, 0xad, 0x2b,
+    ] },
+    CipherVector { mode: WordMode::WordXor, index_key: 0x00a5df8aa9485cd3, index: 0xa43089f56df98340, ciphertext: [
+        0x49, 0x13, 0xd3, 0xc0, 0xfc, 0xb3, 0x9d, 0xbd, 0x2b, 0x42, 0x8b, 0xdd, 0x7b, 0x4b, 0xe0, 0xf9,
+        0xb4, 0xa5, 0x72, 0xfc, 0x66, 0xa8, 0x12, 0x12, 0xdf, 0xd8, 0x6c, 0xd6, 0xc5, 0xbc, 0x9a, 0x5f,
+        0xe7, 0x61, 0x59, 0x00, 0x72, 0x57, 0xc2, 0xc2, 0x6c, 0x14, 0x20, 0x06, 0x5d, 0xf7, 0x26, 0xe3,
+        0x52, 0x76, 0xfb, 0x7a, 0x28, 0x75, 0xbe, 0x88, 0xf1, 0x66, 0xec, 0xfa, 0xfb, 0x52, 0x76, 0x1a,
+        0xf1, 0xc3, 0xb6, 0x13, 0x5c, 0x50, 0xa1, 0x2f, 0x23, 0x12, 0xbf, 0xbc, 0xe7, 0x4e, 0x97, 0x55,
+        0xc1, 0x24, 0xdc, 0xf9, 0x26, 0xce, 0x0f, 0x2d, 0xc9, 0xa8, 0x7b, 0x1a, 0xbe, 0xc1, 0x92, 0x5b,
+        0x86, 0xfa, 0xd9, 0x6f, 0xa6, 0x8d, 0x11, 0x35, 0x25, 0x3f, 0x53, 0xb8, 0x78, 0xb3, 0xcc, 0x6a,
+        0xf7, 0xe4, 0x75, 0xa7, 0x3b, 0x5b, 0x19, 0x71, 0x83, 0x5d, 0x75, 0x2c, 0x8e, 0xbf, 0x67, 0xda,
+        0xe6, 0xd5, 0x4d, 0x8c, 0x9d, 0x27, 0x0e, 0x6f, 0xa7, 0x5b, 0x4b, 0x39, 0x26, 0xd9, 0xa5, 0x23,
+        0xd7, 0xe1, 0x0e, 0x65, 0x3f, 0x48, 0x5b, 0xa9, 0x23, 0xed, 0x8f, 0x99, 0xc9, 0x78, 0x75, 0x5f,
+        0xec, 0xa4, 0xa1, 0x8f, 0x5d, 0x12, 0x42, 0x6a, 0x13, 0x12, 0x01, 0x77, 0x85, 0xfd, 0x99, 0x95,
+        0xf7, 0x8e, 0xc4, 0xfd, 0xf6, 0x34, 0x5d, 0x82, 0x41, 0x3c, 0xb0, 0x31, 0xb0, 0x33, 0x64, 0x46,
+        0x2f, 0xb3, 0x7b, 0xeb, 0x4e, 0xe1, 0x08, 0xe3, 0x1b, 0xe5, 0x95, 0x6b, 0xd3, 0x81, 0x5c, 0xc4,
+        0x31, 0xdc, 0xd9, 0xb9, 0x7d, 0x6d, 0xb9, 0xc9, 0xc7, 0xcb, 0xdc, 0x80, 0xdb, 0x61, 0xc8, 0xb5,
+        0xe7, 0xa7, 0x97, 0xff, 0xe6, 0x7d, 0x70, 0x0c, 0xa5, 0xdd, 0x21, 0x1b, 0x84, 0xb9, 0x8d, 0xfd,
+        0xb7, 0xeb, 0x5d, 0x71, 0xf6, 0x3c, 0xbb, 0xfa,
+    ] },
+];
+
+pub const TAG31_1: [TagVector; 8] = [
+    TagVector { index: 0x653afc83, tag: 0, raw: 0x653afc83 },
+    TagVector { index: 0x0d112161, tag: 1, raw: 0x8d112161 },
+    TagVector { index: 0x5e7a4b03, tag: 1, raw: 0xde7a4b03 },
+    TagVector { index: 0x4574f6f7, tag: 1, raw: 0xc574f6f7 },
+    TagVector { index: 0x1d32d2ed, tag: 1, raw: 0x9d32d2ed },
+    TagVector { index: 0x2dc328df, tag: 1, raw: 0xadc328df },
+    TagVector { index: 0x01df5e82, tag: 0, raw: 0x01df5e82 },
+    TagVector { index: 0x0b5e9d9f, tag: 0, raw: 0x0b5e9d9f },
+];
+
+pub const HASH: [HashVector; 8] = [
+    HashVector { input: 0x82a9e0f81e1d09af, output: 0x13d3e14172a40179 },
+    HashVector { input: 0x4c61a405d0aa81fc, output: 0x29ef496b70ac0cb0 },
+    HashVector { input: 0xf8cdaeec2baaf59b, output: 0x41bde4cef1ace614 },
+    HashVector { input: 0xcbd1ddcd7e71a1da, output: 0x1f3ec1bdf3a33257 },
+    HashVector { input: 0x5955eef5de3d700b, output: 0xcbfc28e61d27512b },
+    HashVector { input: 0x28db4786cbb20a1d, output: 0x34b9a0441f3767a4 },
+    HashVector { input: 0x32ab85c05ecc2bd4, output: 0x236297ba584bf148 },
+    HashVector { input: 0x6ea37b189f8f7b29, output: 0x9f0a21addb7321a5 },
+];
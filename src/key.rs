@@ -1,17 +1,17 @@
+use core::marker::PhantomData;
 use core::mem::size_of;
 use core::ops::{BitXorAssign, Shl, Shr};
-#[cfg(feature = "std")]
-use lazy_static::lazy_static;
-#[cfg(feature = "std")]
-use std::{collections::HashMap, sync::Mutex};
+
+use crate::{FastMod, Pod};
 
 /// A type that is safe to use as a word in a block or key
 ///
 /// # Safety
-/// 1. All bit patterns of implementing types must be valid
+/// 1. All bit patterns of implementing types must be valid, which [`Pod`] already requires
 /// 2. Implementing types must require the alignment of `Self` to be less than or equal to 8 bytes
 pub unsafe trait Word:
-    Copy
+    Pod
+    + Copy
     + From<u8>
     + BitXorAssign
     + Shl<usize, Output = Self>
@@ -19,6 +19,11 @@ pub unsafe trait Word:
     + core::fmt::Debug
     + core::fmt::Display
 {
+    /// Converts `self` from native to little endian byte order, for writing to the wire
+    fn to_le(self) -> Self;
+
+    /// Converts `word` from little endian to native byte order, after reading it from the wire
+    fn from_le(word: Self) -> Self;
 }
 
 /// Represents an `N` element key of type `W`.
@@ -26,22 +31,41 @@ pub unsafe trait Word:
 #[repr(align(8))]
 pub struct Key<const N: usize>([u8; N]);
 
-/// The symmetric key used for both encryption and decryption
-pub const KEY: Key<53280> = Key::new(*include_bytes!("../private/key.bin"));
+/// The symmetric key used for both encryption and decryption.
+///
+/// A `static` rather than a `const`, so that there is only one copy of it in the binary and ciphers
+/// in `static`s can borrow it
+pub static KEY: Key<53280> = Key::new(*include_bytes!("../private/key.bin"));
 
-#[cfg(feature = "std")]
-lazy_static! {
-    static ref FREQ: Mutex<HashMap<usize, usize>> = Mutex::new(HashMap::new());
+/// Precomputed reciprocals for reducing offsets into an `N` byte key when taking `L` element
+/// subkeys of type `W`.
+///
+/// These are associated constants, so they are computed at compile time once per key size, word
+/// type, and subkey length. The modulus in [`Key::subkey`] and the bit offset path of
+/// [`GenericCipher`](crate::GenericCipher) is then a multiply and shift for any key length, even
+/// when the optimizer is not run. See [`FastMod`] for the uniformity guarantee
+pub(crate) struct SubkeyReducers<W, const N: usize, const L: usize>(PhantomData<W>);
+
+impl<W: Word, const N: usize, const L: usize> SubkeyReducers<W, N, L> {
+    /// Reduces word offsets to the number of positions an `L` element subkey can start at
+    pub(crate) const WORDS: FastMod =
+        FastMod::new(at_least_one((N / size_of::<W>() + 1).saturating_sub(L)));
+
+    /// Reduces bit offsets to the number of bit positions a bit shifted `L` element subkey can
+    /// start at. The shifted subkey may straddle one extra word, so it cannot start in the last `L`
+    /// words
+    pub(crate) const BITS: FastMod = FastMod::new(at_least_one(
+        (N / size_of::<W>()).saturating_sub(L) * size_of::<W>() * 8,
+    ));
 }
 
-#[cfg(test)]
-pub fn print_freq() {
-    let lock = FREQ.lock().unwrap();
-    let mut vec: Vec<(_, _)> = lock.iter().collect();
-    vec.sort_unstable();
-
-    for (k, v) in vec {
-        println!("{}: {}", k, v);
+/// Keys too small for a subkey are rejected at runtime before reducing, so avoid failing const
+/// evaluation for them
+const fn at_least_one(n: usize) -> u64 {
+    if n == 0 {
+        1
+    } else {
+        n as u64
     }
 }
 
@@ -71,77 +95,59 @@ impl<const N: usize> Key<N> {
     /// Returns a slice len `key_len` of this key based on word offset module the key length
     /// `L` is the number of elements returned
     pub fn subkey<W: Word, const L: usize>(&self, word_offset: usize) -> &[W; L] {
-        let key_elements = self.check_element_length::<W, L>();
+        self.subkey_at(self.subkey_offset::<W, L>(word_offset as u64))
+    }
+
+    /// Reduces `word_offset` to the offset of the first element of an `L` element subkey
+    pub(crate) fn subkey_offset<W: Word, const L: usize>(&self, word_offset: u64) -> usize {
+        self.check_element_length::<W, L>();
 
         // We need to find `L` contiguous elements, so the maximum index (exclusive) is `L`
-        // less than the total length of the key
-        let max_index = (key_elements + 1) - L;
-
-        // Ensure offset is in range
-        let offset = word_offset % max_index;
-
-        // SAFETY:
-        // `W` is only imelemented for types with an alignment of 8 bytes or less, and becasue Self
-        // is aligned to 8 byte bounderies via #[reper(align(8))], the resulting pointer is aligned
-        let ptr: *const W = self.0.as_ptr() as *const W;
-
-        #[cfg(feature = "std")]
-        {
-            let mut lock = FREQ.lock().unwrap();
-            let count = lock.entry(offset).or_insert_with(|| 0);
-            *count += 1;
-        }
+        // less than the total length of the key. This is `word_offset % max_index` using the
+        // precomputed reciprocal of `max_index`
+        SubkeyReducers::<W, N, L>::WORDS.reduce(word_offset) as usize
+    }
 
-        // SAFETY:
-        // 1. Offset is in range by the calculation of `max_index` above
-        // 2. At least `L` elements are readable by the `max_index` calculation
-        // 3. The result is aligned because `ptr` is aligned
-        // 4. The lifetime of the result is 'k because `self` is 'k
-        let subkey_start = unsafe { ptr.add(offset) };
+    /// Returns the `L` element subkey starting at `offset`, which must have been reduced by
+    /// [`Key::subkey_offset`]
+    pub(crate) fn subkey_at<W: Word, const L: usize>(&self, offset: usize) -> &[W; L] {
+        let key_elements = self.check_element_length::<W, L>();
+        assert!(offset + L <= key_elements, "Subkey offset out of range");
+        self.as_words()[offset..offset + L].try_into().unwrap()
+    }
 
-        // SAFETY:
-        // 1. The layout of a array type ([T; N]) has the same alignment requirements of T, and has
-        //    the size of size_of::<T>() * N
-        // 2. The lifetime of `self.0` is 'self, so the lifetime elision knows that the returned
-        //    lifetime is 'self
-        //
-        // See: https://doc.rust-lang.org/reference/type-layout.html#array-layout
-        unsafe { &*(subkey_start as *const [W; L]) }
+    /// Returns the raw bytes of this key
+    pub const fn as_bytes(&self) -> &[u8; N] {
+        &self.0
     }
 
+    /// Returns this key as words of type `W`. Trailing bytes that do not fill a whole word are
+    /// left out
     pub fn as_words<W: Word>(&self) -> &[W] {
-        let ptr: *const u8 = self.0.as_ptr();
-        let ptr: *const W = ptr as *const _;
-        let len = self.0.len() / size_of::<W>();
-
-        // SAFETY:
-        // 1. ptr is readabel for up to len elements, because each element is `size_of::<W>()`,
-        //    bytes
-        // 2. ptr is aligned for `W`, because `Word` is only implemented for types < 8 bytes large,
-        //    and `Self` is aligned to at least an 8 byte boundry
-        // 3. The lifetime of `self.0` is 'self, so the lifetime elision knows that the returned
-        //    lifetime is 'self
-        //
-        unsafe { core::slice::from_raw_parts(ptr, len) }
+        let len = N / size_of::<W>() * size_of::<W>();
+        // `Self` is aligned to 8 bytes, which is at least the alignment of any `Word`
+        crate::pod::cast_slice(&self.0[..len])
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
     use super::*;
+    use std::vec::Vec;
 
     #[test]
     fn key_len() {
-        // 53280 is a special number.
-        // It is equal to `2^15*(1+5/8)+32`
-        // We want this so that when the optimizer looks at the calculation of `max_index` in `subkey`,
-        // it sees that its value is `2^15*(1+5/8)` which should reduce the modulus to bitwise
-        // instructions (please compiler)
+        // 53280 is equal to `2^15*(1+5/8)+32`.
+        // This was chosen so the optimizer could reduce the modulus in `subkey` to bitwise
+        // instructions. Offsets are now reduced with a precomputed reciprocal (see `FastMod`), so
+        // any key length is equally fast
         assert_eq!(KEY.0.len(), 53280);
         assert_eq!(KEY.0.len() - 32, 2usize.pow(15) * 13 / 8);
     }
 
     #[test]
+    #[allow(clippy::useless_conversion)]
     fn subkey() {
         const KEY_LEN: usize = 32;
         let key: [u8; KEY_LEN] = (0u8..KEY_LEN as u8)
@@ -173,13 +179,55 @@ mod tests {
         let zst = key.subkey::<u32, 0>(0);
         assert!(zst.is_empty());
     }
+
+    #[test]
+    fn subkey_offset_non_power_of_two() {
+        // Reduction must match `%` for a key length that is not a power of two
+        let key = Key::new([0u8; 52]);
+        for i in (0..10_000).chain(usize::MAX - 100..=usize::MAX) {
+            assert_eq!(key.subkey_offset::<u32, 7>(i as u64), i % 7);
+            assert_eq!(key.subkey_offset::<u16, 5>(i as u64), i % 22);
+        }
+    }
 }
 
 /// SAFETY: u8 has no invalid bit patterns
-unsafe impl Word for u8 {}
+unsafe impl Word for u8 {
+    fn to_le(self) -> Self {
+        u8::to_le(self)
+    }
+
+    fn from_le(word: Self) -> Self {
+        u8::from_le(word)
+    }
+}
 /// SAFETY: u16 has no invalid bit patterns
-unsafe impl Word for u16 {}
+unsafe impl Word for u16 {
+    fn to_le(self) -> Self {
+        u16::to_le(self)
+    }
+
+    fn from_le(word: Self) -> Self {
+        u16::from_le(word)
+    }
+}
 /// SAFETY: u32 has no invalid bit patterns
-unsafe impl Word for u32 {}
+unsafe impl Word for u32 {
+    fn to_le(self) -> Self {
+        u32::to_le(self)
+    }
+
+    fn from_le(word: Self) -> Self {
+        u32::from_le(word)
+    }
+}
 /// SAFETY: u64 has no invalid bit patterns
-unsafe impl Word for u64 {}
+unsafe impl Word for u64 {
+    fn to_le(self) -> Self {
+        u64::to_le(self)
+    }
+
+    fn from_le(word: Self) -> Self {
+        u64::from_le(word)
+    }
+}
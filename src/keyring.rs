@@ -0,0 +1,183 @@
+//! Fixed capacity storage for several ciphers identified by a key ID.
+//!
+//! Used when rotating keys, so that traffic encrypted with both the old and new keys can be
+//! decrypted during the overlap. The key ID is carried in the [`Tag`](crate::alg1::Tag) bits of
+//! an indexed block, so receivers pick the right key automatically.
+
+use crate::Tag;
+use core::fmt;
+use core::marker::PhantomData;
+
+/// Errors returned by [`Keyring`] operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyringError {
+    /// No key with this ID is held by the keyring. It was either never inserted or has been retired
+    UnknownKeyId(usize),
+    /// A key with this ID is already held by the keyring
+    DuplicateKeyId(usize),
+    /// The key ID does not fit in the tag bits of the block, so no block could select this key
+    KeyIdOutOfRange(usize),
+    /// All slots of the keyring are in use. Retire an old key before inserting a new one
+    Full,
+}
+
+impl fmt::Display for KeyringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyringError::UnknownKeyId(id) => write!(f, "unknown key id {}", id),
+            KeyringError::DuplicateKeyId(id) => write!(f, "key id {} is already in use", id),
+            KeyringError::KeyIdOutOfRange(id) => {
+                write!(f, "key id {} does not fit in the tag bits", id)
+            }
+            KeyringError::Full => write!(f, "keyring is full"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KeyringError {}
+
+/// Holds up to `CAP` ciphers of type `C`, each identified by a key ID stored in the tag bits of
+/// blocks with tags of type `T`.
+///
+/// No allocation is performed, so this is usable without `std`.
+pub struct Keyring<T, C, const CAP: usize> {
+    slots: [Option<(usize, C)>; CAP],
+    _tag: PhantomData<T>,
+}
+
+impl<T: Tag, C, const CAP: usize> Keyring<T, C, CAP> {
+    /// Creates an empty keyring
+    pub fn new() -> Self {
+        Self {
+            slots: core::array::from_fn(|_| None),
+            _tag: PhantomData,
+        }
+    }
+
+    /// Adds `cipher` to this keyring under `id`.
+    ///
+    /// Returns Err if `id` does not fit in [`Tag::tag_bits_count`] bits, if `id` is already in use
+    /// or if there are no free slots left
+    pub fn insert(&mut self, id: usize, cipher: C) -> Result<(), KeyringError> {
+        if id.checked_shr(T::tag_bits_count() as u32).unwrap_or(0) != 0 {
+            return Err(KeyringError::KeyIdOutOfRange(id));
+        }
+        if self.contains(id) {
+            return Err(KeyringError::DuplicateKeyId(id));
+        }
+        let slot = self
+            .slots
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(KeyringError::Full)?;
+        *slot = Some((id, cipher));
+        Ok(())
+    }
+
+    /// Removes the cipher with `id` from this keyring, returning it.
+    ///
+    /// Blocks using `id` can no longer be decrypted using this keyring afterwards
+    pub fn retire(&mut self, id: usize) -> Result<C, KeyringError> {
+        self.slots
+            .iter_mut()
+            .find(|slot| matches!(slot, Some((slot_id, _)) if *slot_id == id))
+            .and_then(Option::take)
+            .map(|(_, cipher)| cipher)
+            .ok_or(KeyringError::UnknownKeyId(id))
+    }
+
+    /// Returns the cipher stored under `id`
+    pub fn get(&self, id: usize) -> Result<&C, KeyringError> {
+        self.slots
+            .iter()
+            .flatten()
+            .find(|(slot_id, _)| *slot_id == id)
+            .map(|(_, cipher)| cipher)
+            .ok_or(KeyringError::UnknownKeyId(id))
+    }
+
+    /// Returns true if a cipher is stored under `id`
+    pub fn contains(&self, id: usize) -> bool {
+        self.get(id).is_ok()
+    }
+
+    /// Returns the IDs of all keys currently held, in slot order
+    pub fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.slots.iter().flatten().map(|(id, _)| *id)
+    }
+
+    /// Returns the number of keys currently held
+    pub fn len(&self) -> usize {
+        self.ids().count()
+    }
+
+    /// Returns true if no keys are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Tag, C, const CAP: usize> Default for Keyring<T, C, CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Tag28_4, Tag31_1};
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn insert_get_retire() {
+        let mut keyring: Keyring<Tag28_4, &str, 2> = Keyring::new();
+        assert!(keyring.is_empty());
+        assert_eq!(keyring.get(0), Err(KeyringError::UnknownKeyId(0)));
+
+        keyring.insert(0, "old").unwrap();
+        keyring.insert(1, "new").unwrap();
+        assert_eq!(keyring.len(), 2);
+        assert_eq!(keyring.get(0), Ok(&"old"));
+        assert_eq!(keyring.get(1), Ok(&"new"));
+        assert_eq!(keyring.insert(2, "newer"), Err(KeyringError::Full));
+
+        assert_eq!(keyring.retire(0), Ok("old"));
+        assert_eq!(keyring.retire(0), Err(KeyringError::UnknownKeyId(0)));
+        assert_eq!(keyring.get(0), Err(KeyringError::UnknownKeyId(0)));
+        assert_eq!(keyring.ids().collect::<Vec<_>>(), vec![1]);
+
+        // The retired slot can be reused
+        keyring.insert(2, "newer").unwrap();
+        assert_eq!(keyring.get(2), Ok(&"newer"));
+    }
+
+    #[test]
+    fn duplicate_id() {
+        let mut keyring: Keyring<Tag28_4, u32, 4> = Keyring::new();
+        keyring.insert(7, 1).unwrap();
+        assert_eq!(keyring.insert(7, 2), Err(KeyringError::DuplicateKeyId(7)));
+        assert_eq!(keyring.get(7), Ok(&1));
+    }
+
+    #[test]
+    fn id_out_of_range() {
+        let mut keyring: Keyring<Tag31_1, u32, 4> = Keyring::new();
+        keyring.insert(1, 1).unwrap();
+        assert_eq!(keyring.insert(2, 2), Err(KeyringError::KeyIdOutOfRange(2)));
+        assert_eq!(
+            keyring.insert(usize::MAX, 3),
+            Err(KeyringError::KeyIdOutOfRange(usize::MAX))
+        );
+        assert_eq!(keyring.len(), 1);
+
+        let mut keyring: Keyring<Tag28_4, u32, 4> = Keyring::new();
+        keyring.insert(15, 1).unwrap();
+        assert_eq!(
+            keyring.insert(16, 2),
+            Err(KeyringError::KeyIdOutOfRange(16))
+        );
+    }
+}
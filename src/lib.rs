@@ -19,7 +19,8 @@
 //! rng.fill_bytes(&mut key_bytes);
 //! let key = Key::new(key_bytes);
 //!
-//! // We also need an index key that is used to encrypt the index when sent in the clear
+//! // We also need an index key that is mixed into the index before it selects part of the key.
+//! // To also hide the index when it is sent in the clear, see `HeaderPermutation`
 //! let mut index_key = [0u8; 4];
 //! rng.fill_bytes(&mut index_key);
 //! let index_key = u32::from_ne_bytes(index_key);
@@ -35,12 +36,94 @@
 //! cipher.cipher_block(index, &mut block);
 //! assert_eq!(block.as_ref(), original_block.as_ref());
 //! ```
+//!
+//! # Features
+//!
+//! The crate is split into tiers, so that it can be used on targets without an operating system or
+//! a heap:
+//! - Without default features only `core` is used. Every algorithm, block type, [`Keyring`],
+//!   [`fec`] and [`kat::self_test`] work without allocating, on 32 bit targets as well as 64 bit
+//!   ones
+//! - `alloc` adds `shares`, which returns heap allocated key shares
+//! - `std`, the default, adds `framing`, usage histograms and `std::error::Error` impls
+//!
+//! `no_std_test/` is a `no_std` binary without an allocator that checks the `core` tier
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(test)]
+extern crate std;
+
+mod fastmod;
+pub use fastmod::FastMod;
+
+mod pod;
+pub use pod::Pod;
+
+mod splitmix;
 
 mod key;
 pub use key::{Key, Word, KEY};
 
 mod algorithm;
-pub use algorithm::{GenericCipher, GenericCipherBlock, Index};
+pub use algorithm::{GenericCipher, GenericCipherBlock, Index, WordMode};
+
+mod usage;
+pub use usage::{NoObserver, UsageCounter, UsageObserver};
+#[cfg(feature = "std")]
+pub use usage::{UsageHistogram, UsageStats};
+
+mod tag;
+pub use tag::{Tag, Tag24_8, Tag28_4, Tag30_2, Tag31_1, Tag56_8, TagBits};
+
+pub mod crc;
+
+mod wire;
+pub use wire::{Packed, WireBytes, WireError};
+
+mod permutation;
+pub use permutation::{HeaderPermutation, HeaderWord};
+
+mod keyring;
+pub use keyring::{Keyring, KeyringError};
+
+mod block;
+#[cfg(feature = "serde")]
+mod serialize;
+pub use block::{
+    Align8, BlockWireBytes, ChecksumError, ChecksummedBlock, ChecksummedWireBytes,
+    GenericIndexedBlock, NaturalAlign, WordCipher,
+};
+
+mod cipher;
+pub use cipher::{BlockCipher, CipherError, DynBlockCipher};
+
+mod header;
+pub use header::{cipher_message, AlgorithmId, Header, HeaderError, HEADER_SIZE, HEADER_VERSION};
 
 pub mod alg1;
 pub mod alg2;
+pub mod alg3;
+pub mod chacha;
+
+mod hardened;
+pub use hardened::Hardened;
+
+pub mod fec;
+#[cfg(feature = "std")]
+pub mod framing;
+mod gf256;
+pub mod kat;
+#[cfg(feature = "alloc")]
+pub mod shares;
+
+/// The step between inputs visited by exhaustive tests. Every input is visited, except under Miri,
+/// which interprets tests too slowly and only visits every `miri_step`th input
+#[cfg(test)]
+pub(crate) const fn test_step(miri_step: usize) -> usize {
+    if cfg!(miri) {
+        miri_step
+    } else {
+        1
+    }
+}
@@ -0,0 +1,160 @@
+//! Keyed, invertible permutation of block headers, so that the index and tag bits of a block are
+//! not sent in the clear.
+//!
+//! Indices are usually message counters, so sending them as is leaks how many messages were sent.
+//! A [`HeaderPermutation`] maps each header to a different header using a balanced Feistel
+//! network. Every header maps to exactly one other header, so the receiver can always recover the
+//! index and tag bits before decrypting.
+//!
+//! ```
+//! use encryption::{Key, HeaderPermutation, Tag, alg1::{Algorithm1, IndexedBlock}};
+//!
+//! let key = Key::new([7u8; 64]);
+//! let cipher = Algorithm1::new(&key, 0x1234);
+//! // Use a secret, randomly generated header key shared by both ends
+//! let permutation = HeaderPermutation::new(0xDEAD_BEEF_CAFE_F00D);
+//!
+//! let mut block = IndexedBlock::new();
+//! block.tag().set_index(5);
+//! block.do_cipher(&cipher);
+//! block.encode_header(&permutation);
+//! let wire = block.to_wire_bytes();
+//!
+//! let mut received = IndexedBlock::from_wire_bytes(&wire).unwrap();
+//! received.decode_header(&permutation);
+//! assert_eq!(received.tag().get_index(), 5);
+//! received.do_cipher(&cipher);
+//! assert_eq!(received.data(), &[0; 7]);
+//! ```
+
+use crate::splitmix::SplitMix64;
+
+const ROUNDS: usize = 8;
+
+/// A keyed permutation of `u32` and `u64` headers
+#[derive(Clone)]
+pub struct HeaderPermutation {
+    round_keys: [u64; ROUNDS],
+}
+
+/// A header word that can be permuted by a [`HeaderPermutation`]
+pub trait HeaderWord: Copy {
+    /// Applies `permutation` to `self`
+    fn encode(self, permutation: &HeaderPermutation) -> Self;
+
+    /// Inverts [`HeaderWord::encode`]
+    fn decode(self, permutation: &HeaderPermutation) -> Self;
+}
+
+impl HeaderPermutation {
+    /// Creates a permutation from a secret `key`. The key should be random and independent of the
+    /// block key and index key
+    pub const fn new(key: u64) -> Self {
+        let mut round_keys = [0u64; ROUNDS];
+        // SplitMix64, so that similar keys give unrelated round keys
+        let mut state = SplitMix64(key);
+        let mut i = 0;
+        while i < ROUNDS {
+            round_keys[i] = state.next();
+            i += 1;
+        }
+        Self { round_keys }
+    }
+
+    /// Permutes `header`
+    pub fn encode<H: HeaderWord>(&self, header: H) -> H {
+        header.encode(self)
+    }
+
+    /// Recovers the header that was permuted by [`HeaderPermutation::encode`]
+    pub fn decode<H: HeaderWord>(&self, header: H) -> H {
+        header.decode(self)
+    }
+}
+
+/// The Feistel round function. Mixes half of the header with a round key
+fn round(half: u64, round_key: u64) -> u64 {
+    let mut z = (half ^ round_key).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z ^= z >> 29;
+    z = z.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z ^ (z >> 32)
+}
+
+macro_rules! impl_header_word {
+    ($ty:ty, $half:ty) => {
+        impl HeaderWord for $ty {
+            fn encode(self, permutation: &HeaderPermutation) -> Self {
+                const HALF_BITS: u32 = <$half>::BITS;
+                let mut left = (self >> HALF_BITS) as $half;
+                let mut right = self as $half;
+                for &key in permutation.round_keys.iter() {
+                    let next = left ^ round(right as u64, key) as $half;
+                    left = right;
+                    right = next;
+                }
+                ((left as $ty) << HALF_BITS) | right as $ty
+            }
+
+            fn decode(self, permutation: &HeaderPermutation) -> Self {
+                const HALF_BITS: u32 = <$half>::BITS;
+                let mut left = (self >> HALF_BITS) as $half;
+                let mut right = self as $half;
+                for &key in permutation.round_keys.iter().rev() {
+                    let previous = right ^ round(left as u64, key) as $half;
+                    right = left;
+                    left = previous;
+                }
+                ((left as $ty) << HALF_BITS) | right as $ty
+            }
+        }
+    };
+}
+
+impl_header_word!(u32, u16);
+impl_header_word!(u64, u32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{RngCore, SeedableRng};
+
+    #[test]
+    fn round_trip() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        for _ in (0..100).step_by(crate::test_step(10)) {
+            let permutation = HeaderPermutation::new(rng.next_u64());
+            for _ in (0..100).step_by(crate::test_step(10)) {
+                let header = rng.next_u32();
+                assert_eq!(permutation.decode(permutation.encode(header)), header);
+                let header = rng.next_u64();
+                assert_eq!(permutation.decode(permutation.encode(header)), header);
+            }
+            for header in [0, 1, u32::MAX] {
+                assert_eq!(permutation.decode(permutation.encode(header)), header);
+            }
+        }
+    }
+
+    #[test]
+    fn hides_counters() {
+        let permutation = HeaderPermutation::new(1);
+        let mut seen = std::collections::HashSet::new();
+        let mut sequential = 0;
+        let mut previous = permutation.encode(0u32);
+        for header in 0..(1 << 16) / crate::test_step(64) as u32 {
+            let encoded = permutation.encode(header);
+            // A permutation never maps two headers to the same value
+            assert!(seen.insert(encoded));
+            if encoded == previous.wrapping_add(1) {
+                sequential += 1;
+            }
+            previous = encoded;
+        }
+        assert!(sequential < 10, "{} sequential headers", sequential);
+
+        // Different keys give different permutations
+        let other = HeaderPermutation::new(2);
+        assert_ne!(permutation.encode(0u64), other.encode(0u64));
+        assert_ne!(permutation.encode(1u32), other.encode(1u32));
+    }
+}
@@ -0,0 +1,135 @@
+//! Views of "plain old data" as bytes and as slices of other plain old data.
+//!
+//! This module holds the only unsafe memory reinterpretation in the crate. Keys, cipher blocks and
+//! indexed blocks are all viewed through [`cast_slice`] and [`cast_slice_mut`], which check length
+//! and alignment at runtime, while [`Pod::NO_PADDING`] rules out padding at compile time:
+//!
+//! ```compile_fail
+//! use encryption::{GenericIndexedBlock, Tag56_8};
+//!
+//! // A 64 bit tag followed by 7 32 bit words is padded to 40 bytes, so it has no byte view
+//! let block: GenericIndexedBlock<Tag56_8, u32, 7> = GenericIndexedBlock::new();
+//! block.as_bytes();
+//! ```
+
+use core::mem::{align_of, size_of};
+
+/// A type that can be viewed as bytes, and created from any bytes of the right length
+///
+/// # Safety
+/// 1. All bit patterns of implementing types must be valid
+/// 2. Implementing types must not contain padding, pointers or interior mutability. Types whose
+///    padding depends on generic parameters must override [`Pod::NO_PADDING`] to check for it
+pub unsafe trait Pod: Sized {
+    /// Evaluated by every byte view of `Self`, so that a layout with padding fails to compile.
+    /// Implementations for types that cannot contain padding can use the default
+    const NO_PADDING: () = ();
+}
+
+/// SAFETY: u8 has no invalid bit patterns or padding
+unsafe impl Pod for u8 {}
+/// SAFETY: u16 has no invalid bit patterns or padding
+unsafe impl Pod for u16 {}
+/// SAFETY: u32 has no invalid bit patterns or padding
+unsafe impl Pod for u32 {}
+/// SAFETY: u64 has no invalid bit patterns or padding
+unsafe impl Pod for u64 {}
+/// SAFETY: Arrays have the same alignment as their elements and no padding between elements
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {
+    const NO_PADDING: () = T::NO_PADDING;
+}
+
+/// Reinterprets `slice` as a slice of `B`
+///
+/// # Panics
+/// If `slice` is not aligned for `B`, or its length in bytes is not a multiple of the size of `B`
+pub(crate) fn cast_slice<A: Pod, B: Pod>(slice: &[A]) -> &[B] {
+    let len = checked_len::<A, B>(slice.as_ptr() as usize, slice.len());
+    // SAFETY:
+    // 1. The pointer is aligned for `B` and `len` elements of `B` cover exactly the bytes of
+    //    `slice`, by `checked_len`
+    // 2. Every byte is initialized because neither `A` nor `B` has padding, and every bit pattern
+    //    is a valid `B` (`Pod` is an unsafe trait)
+    // 3. The result borrows `slice`, so it cannot outlive it or alias a mutable reference
+    unsafe { core::slice::from_raw_parts(slice.as_ptr() as *const B, len) }
+}
+
+/// Reinterprets `slice` as a mutable slice of `B`
+///
+/// # Panics
+/// If `slice` is not aligned for `B`, or its length in bytes is not a multiple of the size of `B`
+pub(crate) fn cast_slice_mut<A: Pod, B: Pod>(slice: &mut [A]) -> &mut [B] {
+    let len = checked_len::<A, B>(slice.as_ptr() as usize, slice.len());
+    // SAFETY: As in `cast_slice`. Any `B` written through the result is also a valid `A`, because
+    // every bit pattern is a valid `A`
+    unsafe { core::slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut B, len) }
+}
+
+/// Returns the bytes of `value`
+pub(crate) fn bytes_of<T: Pod>(value: &T) -> &[u8] {
+    cast_slice(core::slice::from_ref(value))
+}
+
+/// Returns the bytes of `value` mutably
+pub(crate) fn bytes_of_mut<T: Pod>(value: &mut T) -> &mut [u8] {
+    cast_slice_mut(core::slice::from_mut(value))
+}
+
+/// Returns the length of a slice of `B` covering the same bytes as `len` elements of `A` at
+/// `address`, checking the layout of both types
+fn checked_len<A: Pod, B: Pod>(address: usize, len: usize) -> usize {
+    #[allow(clippy::let_unit_value)]
+    let ((), ()) = (A::NO_PADDING, B::NO_PADDING);
+    let bytes = len * size_of::<A>();
+    if size_of::<B>() == 0 {
+        assert_eq!(bytes, 0, "Cannot cast bytes to a zero sized type");
+        return 0;
+    }
+    assert_eq!(
+        address % align_of::<B>(),
+        0,
+        "Slice is not aligned for the target type"
+    );
+    assert_eq!(
+        bytes % size_of::<B>(),
+        0,
+        "Slice length is not a multiple of the target size"
+    );
+    bytes / size_of::<B>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn casts() {
+        let mut words = [0x0403_0201u32, 0x0807_0605];
+        let mut expected = [0u8; 8];
+        expected[..4].copy_from_slice(&words[0].to_ne_bytes());
+        expected[4..].copy_from_slice(&words[1].to_ne_bytes());
+        assert_eq!(cast_slice::<u32, u8>(&words), expected);
+        assert_eq!(bytes_of(&words), expected);
+
+        cast_slice_mut::<u32, u8>(&mut words)[..4].copy_from_slice(&[0xFF; 4]);
+        assert_eq!(words, [u32::MAX, 0x0807_0605]);
+        bytes_of_mut(&mut words[1]).fill(0);
+        assert_eq!(words[1], 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn misaligned() {
+        let words = [0u32; 4];
+        let bytes: &[u8] = cast_slice(&words);
+        let _: &[u32] = cast_slice(&bytes[1..13]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrong_length() {
+        let bytes = [0u32; 2];
+        let bytes: &[u8] = cast_slice(&bytes);
+        let _: &[u32] = cast_slice(&bytes[..7]);
+    }
+}
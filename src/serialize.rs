@@ -0,0 +1,259 @@
+//! Serde support, enabled with the `serde` feature.
+//!
+//! Every type is serialized as its bytes: blocks and tags use the little endian wire format, and
+//! [`GenericCipherBlock`] and [`Key`](crate::Key) use their raw bytes. Human readable formats such
+//! as JSON get a lowercase hex string, and binary formats get the bytes directly, so a 32 byte
+//! [`alg1::IndexedBlock`](crate::alg1::IndexedBlock) is 32 bytes plus any length prefix.
+//!
+//! `Key` is only serializable with the separate `serde-key` feature, so that keys are not written
+//! to logs by accident
+
+use core::fmt;
+
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{GenericCipherBlock, GenericIndexedBlock, Tag, TagBits, Word};
+
+/// Formats bytes as lowercase hex
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serializes `bytes` as hex for human readable formats, and as bytes otherwise
+pub(crate) fn serialize_bytes<S: Serializer>(
+    bytes: &[u8],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.collect_str(&Hex(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+/// Deserializes exactly `N` bytes written by [`serialize_bytes`]
+pub(crate) fn deserialize_array<'de, D: Deserializer<'de>, const N: usize>(
+    deserializer: D,
+) -> Result<[u8; N], D::Error> {
+    let mut bytes = [0u8; N];
+    deserialize_into(deserializer, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Deserializes exactly `out.len()` bytes written by [`serialize_bytes`] into `out`
+fn deserialize_into<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    out: &mut [u8],
+) -> Result<(), D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(BytesVisitor(out))
+    } else {
+        deserializer.deserialize_bytes(BytesVisitor(out))
+    }
+}
+
+/// Fills the slice it holds with the deserialized bytes
+struct BytesVisitor<'a>(&'a mut [u8]);
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+impl<'de> Visitor<'de> for BytesVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self.0.len();
+        write!(f, "{} bytes, or a string of {} hex digits", len, len * 2)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<(), E> {
+        if v.len() != self.0.len() * 2 {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        for i in 0..self.0.len() {
+            let pair = &v.as_bytes()[i * 2..i * 2 + 2];
+            match (hex_digit(pair[0]), hex_digit(pair[1])) {
+                (Some(high), Some(low)) => self.0[i] = high << 4 | low,
+                _ => return Err(E::invalid_value(de::Unexpected::Str(v), &self)),
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<(), E> {
+        if v.len() != self.0.len() {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        self.0.copy_from_slice(v);
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let len = self.0.len();
+        for i in 0..len {
+            self.0[i] = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+        }
+        if seq.next_element::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(len + 1, &self));
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> Serialize for GenericCipherBlock<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&self.0, serializer)
+    }
+}
+
+impl<'de, const N: usize> serde::Deserialize<'de> for GenericCipherBlock<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_array(deserializer).map(Self::new)
+    }
+}
+
+/// Serialized as the wire format, see [`GenericIndexedBlock::to_wire_bytes`]
+impl<T: Tag, W: Word, const N: usize, A> Serialize for GenericIndexedBlock<T, W, N, A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&self.to_wire_bytes(), serializer)
+    }
+}
+
+impl<'de, T: Tag + Default, W: Word, const N: usize, A> serde::Deserialize<'de>
+    for GenericIndexedBlock<T, W, N, A>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut wire = Self::new().to_wire_bytes();
+        deserialize_into(deserializer, &mut wire)?;
+        Self::from_wire_bytes(&wire).map_err(de::Error::custom)
+    }
+}
+
+macro_rules! impl_tag_serde {
+    ($ty:ty) => {
+        impl<const INDEX_BITS: usize> Serialize for TagBits<$ty, INDEX_BITS> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize_bytes(&self.to_raw().to_le_bytes(), serializer)
+            }
+        }
+
+        impl<'de, const INDEX_BITS: usize> serde::Deserialize<'de> for TagBits<$ty, INDEX_BITS> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_array(deserializer)
+                    .map(|bytes| Self::from_raw(<$ty>::from_le_bytes(bytes)))
+            }
+        }
+    };
+}
+
+impl_tag_serde!(u32);
+impl_tag_serde!(u64);
+
+#[cfg(feature = "serde-key")]
+impl<const N: usize> Serialize for crate::Key<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(self.as_bytes(), serializer)
+    }
+}
+
+#[cfg(feature = "serde-key")]
+impl<'de, const N: usize> serde::Deserialize<'de> for crate::Key<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_array(deserializer).map(Self::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{alg1, alg2, GenericCipherBlock, GenericIndexedBlock, Tag, Tag28_4, Tag31_1};
+    use serde::{de::DeserializeOwned, Serialize};
+
+    /// Round trips `value` through JSON, which is self describing and human readable, and bincode,
+    /// which is neither. Returns the JSON
+    fn round_trip<T: Serialize + DeserializeOwned>(
+        value: &T,
+        eq: impl Fn(&T, &T) -> bool,
+    ) -> String {
+        let json = serde_json::to_string(value).unwrap();
+        assert!(eq(value, &serde_json::from_str(&json).unwrap()));
+
+        let binary = bincode::serialize(value).unwrap();
+        assert!(eq(value, &bincode::deserialize(&binary).unwrap()));
+        json
+    }
+
+    #[test]
+    fn tag() {
+        let mut tag = Tag31_1::new(0x0102_0304);
+        tag.set_tag(1);
+        let json = round_trip(&tag, |a, b| a == b);
+        assert_eq!(json, "\"04030281\"");
+        // Binary formats store the 4 raw bytes after bincode's 8 byte length prefix
+        assert_eq!(bincode::serialize(&tag).unwrap()[8..], [4, 3, 2, 0x81]);
+    }
+
+    #[test]
+    fn blocks() {
+        let block = GenericCipherBlock::new([0xAB; 28]);
+        let json = round_trip(&block, |a, b| a.0 == b.0);
+        assert_eq!(json, format!("\"{}\"", "ab".repeat(28)));
+        assert_eq!(bincode::serialize(&block).unwrap().len(), 8 + 28);
+
+        let mut block = alg1::IndexedBlock::new();
+        block.tag().set_index(7);
+        block.data_mut()[6] = 0xDEAD_BEEF;
+        let json = round_trip(&block, |a, b| a.to_wire_bytes() == b.to_wire_bytes());
+        assert_eq!(json.len(), 2 + 64);
+        assert!(json.starts_with("\"07000000"));
+        assert!(json.ends_with("efbeadde\""));
+
+        let mut block = alg2::IndexedBlock::new();
+        block.tag().set_index(0x1234);
+        block.tag().set_tag(200);
+        block.data_mut()[30] = u64::MAX;
+        round_trip(&block, |a, b| a.to_wire_bytes() == b.to_wire_bytes());
+        assert_eq!(bincode::serialize(&block).unwrap().len(), 8 + 256);
+
+        // Every layout is serializable, not just the ones used by the algorithms
+        let mut block: GenericIndexedBlock<Tag28_4, u16, 3> = GenericIndexedBlock::new();
+        block.tag().set_tag(0xF);
+        block.data_mut()[0] = 0xBEEF;
+        let json = round_trip(&block, |a, b| a.to_wire_bytes() == b.to_wire_bytes());
+        assert_eq!(json, "\"000000f0efbe00000000\"");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(serde_json::from_str::<Tag31_1>("\"040302\"").is_err());
+        assert!(serde_json::from_str::<Tag31_1>("\"0403028g\"").is_err());
+        assert!(serde_json::from_str::<Tag31_1>("\"04030281ff\"").is_err());
+        assert!(serde_json::from_str::<alg1::IndexedBlock>("\"00\"").is_err());
+
+        let short = bincode::serialize(&[0u8; 3][..]).unwrap();
+        assert!(bincode::deserialize::<Tag31_1>(&short).is_err());
+    }
+
+    #[cfg(feature = "serde-key")]
+    #[test]
+    fn key() {
+        let key = crate::Key::new([0x5Au8; 64]);
+        let json = round_trip(&key, |a, b| a.as_bytes() == b.as_bytes());
+        assert_eq!(json, format!("\"{}\"", "5a".repeat(64)));
+    }
+}
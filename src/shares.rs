@@ -0,0 +1,543 @@
+//! Splitting of a [`Key`] into shares, so that no single holder of a share learns anything about
+//! the key.
+//!
+//! Two schemes are supported:
+//! - [`split_xor`]: n-of-n splitting. Every share is required to rebuild the key
+//! - [`split_shamir`]: k-of-n Shamir secret sharing over GF(256). Any `k` shares rebuild the key
+//!
+//! Each share carries its share index, a random ID shared by every share of the same split, and a
+//! CRC-32 checksum of its contents, and can be stored in a share file using [`Share::to_bytes`]
+//! and [`Share::from_bytes`].
+//!
+//! ```
+//! use encryption::{Key, shares};
+//! use rand::{RngCore, SeedableRng};
+//!
+//! let mut rng = rand::rngs::StdRng::seed_from_u64(0xDEADBEEF);
+//! let mut key_bytes = [0u8; 64];
+//! rng.fill_bytes(&mut key_bytes);
+//! let key = Key::new(key_bytes);
+//!
+//! // Use a cryptographically secure random number generator here!
+//! let shares = shares::split_shamir(&key, 2, 3, |buf| rng.fill_bytes(buf)).unwrap();
+//! let files: Vec<Vec<u8>> = shares.iter().map(|share| share.to_bytes()).collect();
+//!
+//! // Any two of the three files rebuild the key
+//! let received = [
+//!     shares::Share::<64>::from_bytes(&files[2]).unwrap(),
+//!     shares::Share::<64>::from_bytes(&files[0]).unwrap(),
+//! ];
+//! let rebuilt = shares::combine(&received).unwrap();
+//! assert_eq!(rebuilt.as_bytes(), key.as_bytes());
+//! ```
+
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+use crate::crc::crc32;
+use crate::{gf256, Key};
+
+const MAGIC: [u8; 4] = *b"ESHR";
+const VERSION: u8 = 1;
+/// Magic, version, scheme, index, threshold, split ID, and key length
+const HEADER_LEN: usize = 4 + 1 + 1 + 1 + 1 + 8 + 4;
+const CHECKSUM_LEN: usize = 4;
+
+/// The scheme used to split a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// n-of-n splitting where the key is the Xor of all shares
+    Xor,
+    /// k-of-n Shamir secret sharing over GF(256)
+    Shamir,
+}
+
+impl Scheme {
+    fn to_byte(self) -> u8 {
+        match self {
+            Scheme::Xor => 0,
+            Scheme::Shamir => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, ShareError> {
+        match byte {
+            0 => Ok(Scheme::Xor),
+            1 => Ok(Scheme::Shamir),
+            other => Err(ShareError::UnknownScheme(other)),
+        }
+    }
+}
+
+/// Errors returned when splitting, parsing, or combining shares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareError {
+    /// The requested threshold or share count is invalid.
+    /// Both must be non zero, and the threshold cannot exceed the share count
+    InvalidParameters { threshold: u8, count: u8 },
+    /// A share file does not start with the share magic bytes
+    BadMagic,
+    /// A share file was written by an unsupported version
+    UnsupportedVersion(u8),
+    /// A share file uses an unknown splitting scheme
+    UnknownScheme(u8),
+    /// A share file has the wrong length for the key size it is being read as
+    WrongLength { expected: usize, actual: usize },
+    /// The checksum of the share with this index does not match its contents
+    ChecksumMismatch { index: u8 },
+    /// Fewer shares were supplied than are needed to rebuild the key
+    NotEnoughShares { needed: u8, supplied: usize },
+    /// Two shares with the same index were supplied
+    DuplicateIndex(u8),
+    /// The supplied shares were not produced by the same split, because their split IDs, schemes,
+    /// or thresholds differ
+    Inconsistent,
+}
+
+impl fmt::Display for ShareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareError::InvalidParameters { threshold, count } => {
+                write!(f, "invalid threshold {} for {} shares", threshold, count)
+            }
+            ShareError::BadMagic => write!(f, "not a share file"),
+            ShareError::UnsupportedVersion(v) => write!(f, "unsupported share version {}", v),
+            ShareError::UnknownScheme(s) => write!(f, "unknown share scheme {}", s),
+            ShareError::WrongLength { expected, actual } => write!(
+                f,
+                "share file is {} bytes, expected {} bytes",
+                actual, expected
+            ),
+            ShareError::ChecksumMismatch { index } => {
+                write!(f, "checksum mismatch in share {}", index)
+            }
+            ShareError::NotEnoughShares { needed, supplied } => write!(
+                f,
+                "{} shares are needed to rebuild the key, but only {} were supplied",
+                needed, supplied
+            ),
+            ShareError::DuplicateIndex(i) => write!(f, "share {} was supplied twice", i),
+            ShareError::Inconsistent => write!(f, "shares are from different splits"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ShareError {}
+
+/// A single share of an `N` byte key
+#[derive(Clone, PartialEq, Eq)]
+pub struct Share<const N: usize> {
+    scheme: Scheme,
+    index: u8,
+    threshold: u8,
+    split_id: u64,
+    data: Vec<u8>,
+    checksum: u32,
+}
+
+impl<const N: usize> Share<N> {
+    fn new(scheme: Scheme, index: u8, threshold: u8, split_id: u64, data: Vec<u8>) -> Self {
+        debug_assert_eq!(data.len(), N);
+        let mut share = Self {
+            scheme,
+            index,
+            threshold,
+            split_id,
+            data,
+            checksum: 0,
+        };
+        share.checksum = share.compute_checksum();
+        share
+    }
+
+    /// Returns the scheme that produced this share
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    /// Returns the index of this share, starting at 1
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Returns the number of shares needed to rebuild the key
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Returns the random ID of the split that produced this share. Every share of a split has the
+    /// same ID
+    pub fn split_id(&self) -> u64 {
+        self.split_id
+    }
+
+    /// Returns the checksum stored with this share
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    /// Returns Err if the stored checksum does not match the contents of this share
+    pub fn verify(&self) -> Result<(), ShareError> {
+        if self.compute_checksum() == self.checksum {
+            Ok(())
+        } else {
+            Err(ShareError::ChecksumMismatch { index: self.index })
+        }
+    }
+
+    /// Serializes this share into the contents of a share file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + N + CHECKSUM_LEN);
+        self.write_unchecked(&mut bytes);
+        bytes.extend_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Parses the contents of a share file, verifying its checksum
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ShareError> {
+        let expected = HEADER_LEN + N + CHECKSUM_LEN;
+        if bytes.len() < HEADER_LEN {
+            return Err(ShareError::WrongLength {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        if bytes[0..4] != MAGIC {
+            return Err(ShareError::BadMagic);
+        }
+        if bytes[4] != VERSION {
+            return Err(ShareError::UnsupportedVersion(bytes[4]));
+        }
+        let scheme = Scheme::from_byte(bytes[5])?;
+        let index = bytes[6];
+        let threshold = bytes[7];
+        let split_id = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let key_len = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+        if key_len != N || bytes.len() != expected {
+            return Err(ShareError::WrongLength {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+        let data = bytes[HEADER_LEN..HEADER_LEN + N].to_vec();
+        let checksum = u32::from_le_bytes(bytes[HEADER_LEN + N..].try_into().unwrap());
+
+        let share = Self {
+            scheme,
+            index,
+            threshold,
+            split_id,
+            data,
+            checksum,
+        };
+        share.verify()?;
+        Ok(share)
+    }
+
+    /// Writes everything but the checksum
+    fn write_unchecked(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.scheme.to_byte());
+        bytes.push(self.index);
+        bytes.push(self.threshold);
+        bytes.extend_from_slice(&self.split_id.to_le_bytes());
+        bytes.extend_from_slice(&(N as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + N);
+        self.write_unchecked(&mut bytes);
+        crc32(&bytes)
+    }
+}
+
+impl<const N: usize> fmt::Debug for Share<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print share contents
+        f.debug_struct("Share")
+            .field("scheme", &self.scheme)
+            .field("index", &self.index)
+            .field("threshold", &self.threshold)
+            .field("split_id", &self.split_id)
+            .field("checksum", &self.checksum)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Splits `key` into `count` shares, all of which are required to rebuild it.
+///
+/// `fill_random` must fill the buffer it is given with cryptographically secure random bytes
+pub fn split_xor<const N: usize>(
+    key: &Key<N>,
+    count: u8,
+    mut fill_random: impl FnMut(&mut [u8]),
+) -> Result<Vec<Share<N>>, ShareError> {
+    if count == 0 {
+        return Err(ShareError::InvalidParameters {
+            threshold: count,
+            count,
+        });
+    }
+    let split_id = random_split_id(&mut fill_random);
+    let mut last = key.as_bytes().to_vec();
+    let mut shares = Vec::with_capacity(count as usize);
+    for index in 1..count {
+        let mut data = vec![0u8; N];
+        fill_random(&mut data);
+        for (l, d) in last.iter_mut().zip(&data) {
+            *l ^= d;
+        }
+        shares.push(Share::new(Scheme::Xor, index, count, split_id, data));
+    }
+    shares.push(Share::new(Scheme::Xor, count, count, split_id, last));
+    Ok(shares)
+}
+
+/// Returns a random ID for a new split, so that shares of different splits are not combined
+fn random_split_id(fill_random: &mut impl FnMut(&mut [u8])) -> u64 {
+    let mut id = [0u8; 8];
+    fill_random(&mut id);
+    u64::from_le_bytes(id)
+}
+
+/// Splits `key` into `count` shares, any `threshold` of which can rebuild it.
+///
+/// Each byte of the key is the constant term of a random polynomial of degree `threshold - 1`
+/// over GF(256), and share `i` holds the polynomial evaluated at `i`.
+/// `fill_random` must fill the buffer it is given with cryptographically secure random bytes
+pub fn split_shamir<const N: usize>(
+    key: &Key<N>,
+    threshold: u8,
+    count: u8,
+    mut fill_random: impl FnMut(&mut [u8]),
+) -> Result<Vec<Share<N>>, ShareError> {
+    if threshold == 0 || count == 0 || threshold > count {
+        return Err(ShareError::InvalidParameters { threshold, count });
+    }
+    let split_id = random_split_id(&mut fill_random);
+    let degree = threshold as usize - 1;
+    // The coefficients of x^1 through x^degree for every byte of the key
+    let mut coefficients = vec![0u8; degree * N];
+    fill_random(&mut coefficients);
+
+    let secret = key.as_bytes();
+    let shares = (1..=count)
+        .map(|x| {
+            let data = (0..N)
+                .map(|byte| {
+                    // Horner's method, starting with the highest degree coefficient
+                    let poly = &coefficients[byte * degree..(byte + 1) * degree];
+                    let y = poly
+                        .iter()
+                        .rev()
+                        .fold(0u8, |acc, &c| gf256::mul(acc, x) ^ c);
+                    gf256::mul(y, x) ^ secret[byte]
+                })
+                .collect();
+            Share::new(Scheme::Shamir, x, threshold, split_id, data)
+        })
+        .collect();
+    Ok(shares)
+}
+
+/// Rebuilds a key from `shares`.
+///
+/// Every share is verified against its checksum first. For [`Scheme::Xor`] all shares of the
+/// split must be supplied, for [`Scheme::Shamir`] at least the threshold. Extra shares are ignored
+pub fn combine<const N: usize>(shares: &[Share<N>]) -> Result<Key<N>, ShareError> {
+    let first = shares.first().ok_or(ShareError::NotEnoughShares {
+        needed: 1,
+        supplied: 0,
+    })?;
+    let mut seen = [false; 256];
+    for share in shares {
+        share.verify()?;
+        if share.scheme != first.scheme
+            || share.threshold != first.threshold
+            || share.split_id != first.split_id
+        {
+            return Err(ShareError::Inconsistent);
+        }
+        if core::mem::replace(&mut seen[share.index as usize], true) {
+            return Err(ShareError::DuplicateIndex(share.index));
+        }
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(ShareError::NotEnoughShares {
+            needed: first.threshold,
+            supplied: shares.len(),
+        });
+    }
+    let shares = &shares[..first.threshold as usize];
+
+    let mut key = [0u8; N];
+    match first.scheme {
+        Scheme::Xor => {
+            for share in shares {
+                for (k, s) in key.iter_mut().zip(&share.data) {
+                    *k ^= s;
+                }
+            }
+        }
+        Scheme::Shamir => {
+            for share in shares {
+                if share.index == 0 {
+                    return Err(ShareError::Inconsistent);
+                }
+                // Lagrange basis polynomial for this share evaluated at 0.
+                // Subtraction is Xor in GF(256)
+                let weight = shares
+                    .iter()
+                    .filter(|other| other.index != share.index)
+                    .fold(1u8, |acc, other| {
+                        gf256::mul(acc, gf256::div(other.index, other.index ^ share.index))
+                    });
+                for (k, &s) in key.iter_mut().zip(&share.data) {
+                    *k ^= gf256::mul(s, weight);
+                }
+            }
+        }
+    }
+    Ok(Key::new(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{RngCore, SeedableRng};
+
+    fn random_key<const N: usize>(rng: &mut impl RngCore) -> Key<N> {
+        let mut key_bytes = [0u8; N];
+        rng.fill_bytes(&mut key_bytes);
+        Key::new(key_bytes)
+    }
+
+    #[test]
+    fn xor_round_trip() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let key: Key<128> = random_key(&mut rng);
+        let shares = split_xor(&key, 3, |buf| rng.fill_bytes(buf)).unwrap();
+        assert_eq!(shares.len(), 3);
+        for share in &shares {
+            assert_ne!(share.data.as_slice(), key.as_bytes().as_slice());
+        }
+
+        let rebuilt = combine(&shares).unwrap();
+        assert_eq!(rebuilt.as_bytes(), key.as_bytes());
+
+        assert_eq!(
+            combine(&shares[1..]).err(),
+            Some(ShareError::NotEnoughShares {
+                needed: 3,
+                supplied: 2
+            })
+        );
+    }
+
+    #[test]
+    fn shamir_any_threshold_subset() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let key: Key<64> = random_key(&mut rng);
+        let shares = split_shamir(&key, 3, 5, |buf| rng.fill_bytes(buf)).unwrap();
+
+        for a in 0..5 {
+            for b in 0..5 {
+                for c in 0..5 {
+                    if a == b || b == c || a == c {
+                        continue;
+                    }
+                    let subset = [shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                    let rebuilt = combine(&subset).unwrap();
+                    assert_eq!(rebuilt.as_bytes(), key.as_bytes());
+                }
+            }
+        }
+
+        assert_eq!(
+            combine(&shares[..2]).err(),
+            Some(ShareError::NotEnoughShares {
+                needed: 3,
+                supplied: 2
+            })
+        );
+        let duplicated = [shares[0].clone(), shares[1].clone(), shares[0].clone()];
+        assert_eq!(
+            combine(&duplicated).err(),
+            Some(ShareError::DuplicateIndex(1))
+        );
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let key: Key<8> = Key::new([0; 8]);
+        assert!(split_shamir(&key, 3, 2, |_| {}).is_err());
+        assert!(split_shamir(&key, 0, 2, |_| {}).is_err());
+        assert!(split_xor(&key, 0, |_| {}).is_err());
+    }
+
+    #[test]
+    fn share_files() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let key: Key<32> = random_key(&mut rng);
+        let shares = split_shamir(&key, 2, 2, |buf| rng.fill_bytes(buf)).unwrap();
+
+        for share in &shares {
+            let bytes = share.to_bytes();
+            assert_eq!(bytes.len(), HEADER_LEN + 32 + CHECKSUM_LEN);
+            assert_eq!(&Share::<32>::from_bytes(&bytes).unwrap(), share);
+
+            for i in 0..bytes.len() {
+                let mut corrupt = bytes.clone();
+                corrupt[i] ^= 0x10;
+                assert!(Share::<32>::from_bytes(&corrupt).is_err());
+            }
+            let mut corrupt = bytes.clone();
+            corrupt[HEADER_LEN] ^= 1;
+            assert_eq!(
+                Share::<32>::from_bytes(&corrupt),
+                Err(ShareError::ChecksumMismatch {
+                    index: share.index()
+                })
+            );
+
+            assert_eq!(
+                Share::<64>::from_bytes(&bytes),
+                Err(ShareError::WrongLength {
+                    expected: HEADER_LEN + 64 + CHECKSUM_LEN,
+                    actual: bytes.len()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn mixed_splits() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let key: Key<32> = random_key(&mut rng);
+        let other: Key<32> = random_key(&mut rng);
+
+        // Same scheme, threshold and key size, so only the split ID tells the shares apart
+        let shares = split_shamir(&key, 2, 3, |buf| rng.fill_bytes(buf)).unwrap();
+        let other_shares = split_shamir(&other, 2, 3, |buf| rng.fill_bytes(buf)).unwrap();
+        assert!(shares.iter().all(|s| s.split_id() == shares[0].split_id()));
+        assert_ne!(shares[0].split_id(), other_shares[0].split_id());
+        let mixed = [shares[0].clone(), other_shares[1].clone()];
+        assert_eq!(combine(&mixed).err(), Some(ShareError::Inconsistent));
+
+        let shares = split_xor(&key, 2, |buf| rng.fill_bytes(buf)).unwrap();
+        let other_shares = split_xor(&key, 2, |buf| rng.fill_bytes(buf)).unwrap();
+        let mixed = [shares[0].clone(), other_shares[1].clone()];
+        assert_eq!(combine(&mixed).err(), Some(ShareError::Inconsistent));
+
+        // The split ID survives share files
+        let file = Share::<32>::from_bytes(&shares[1].to_bytes()).unwrap();
+        assert_eq!(file.split_id(), shares[1].split_id());
+        assert_eq!(
+            combine(&[shares[0].clone(), file]).unwrap().as_bytes(),
+            key.as_bytes()
+        );
+    }
+}
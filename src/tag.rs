@@ -0,0 +1,259 @@
+//! Tags store a block index together with a few bits of user specified data, such as a message
+//! type or key ID, in a single header word
+
+/// Represents the header bits of a message that contain the index and other user specified data
+pub trait Tag {
+    type IndexTy: crate::Index;
+
+    /// The integer holding the index and tag bits, which is how the tag is stored on the wire
+    type Raw: crate::Word;
+
+    /// Creates a new Tag. The intex bits will be initally set to zero
+    fn new(index: Self::IndexTy) -> Self;
+
+    /// Returns the index of this tag with the tag part removed
+    fn get_index(&self) -> Self::IndexTy;
+
+    fn set_index(&mut self, index: Self::IndexTy);
+
+    /// Returns the tag bits
+    /// Tags wider than `usize` are truncated to their lowest `usize::BITS` bits, the same bits
+    /// that [`set_tag`] can set. Use [`TagBits::tag_raw`] to read every tag bit
+    fn get_tag(&self) -> usize;
+
+    /// Sets the tag bits stored in this tag
+    /// Only the lowest [`tag_bits_count`] bits will be stored and be available with [`get_tag`]
+    /// the higher bits will be discarded. Tag bits above `usize::BITS` are cleared
+    fn set_tag(&mut self, tag: usize);
+
+    /// Returns the number of tag bits this tag supports
+    fn tag_bits_count() -> usize;
+
+    /// Creates a tag from the integer returned by [`Tag::to_raw`]
+    fn from_raw(raw: Self::Raw) -> Self;
+
+    /// Returns the integer holding both the index and the tag bits
+    fn to_raw(&self) -> Self::Raw;
+}
+
+/// A tag stored in a single `T` (`u32` or `u64`), with the lowest `INDEX_BITS` bits holding the
+/// index and the remaining high bits holding the tag
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct TagBits<T, const INDEX_BITS: usize>(T);
+
+/// SAFETY: `TagBits` is a transparent wrapper, so it has the layout of `T`
+unsafe impl<T: crate::Pod, const INDEX_BITS: usize> crate::Pod for TagBits<T, INDEX_BITS> {
+    const NO_PADDING: () = T::NO_PADDING;
+}
+
+/// 31 index bits and 1 tag bit
+pub type Tag31_1 = TagBits<u32, 31>;
+/// 30 index bits and 2 tag bits
+pub type Tag30_2 = TagBits<u32, 30>;
+/// 28 index bits and 4 tag bits
+pub type Tag28_4 = TagBits<u32, 28>;
+/// 24 index bits and 8 tag bits
+pub type Tag24_8 = TagBits<u32, 24>;
+/// 56 index bits and 8 tag bits
+pub type Tag56_8 = TagBits<u64, 56>;
+
+macro_rules! impl_tag_bits {
+    ($ty:ty) => {
+        impl<const INDEX_BITS: usize> TagBits<$ty, INDEX_BITS> {
+            /// The bits of the underlying integer that store the index.
+            /// Fails to compile unless there is at least one index bit and one tag bit
+            pub const INDEX_MASK: $ty = {
+                assert!(
+                    INDEX_BITS > 0 && INDEX_BITS < <$ty>::BITS as usize,
+                    "TagBits needs at least one index bit and one tag bit"
+                );
+                <$ty>::MAX >> (<$ty>::BITS as usize - INDEX_BITS)
+            };
+
+            /// The number of tag bits
+            pub const TAG_BITS: usize = <$ty>::BITS as usize - INDEX_BITS;
+
+            /// Creates a tag from its raw representation, with the tag in the high bits
+            pub const fn from_raw(raw: $ty) -> Self {
+                Self(raw)
+            }
+
+            /// Returns the raw representation of this tag, with the tag in the high bits
+            pub const fn to_raw(self) -> $ty {
+                self.0
+            }
+
+            /// Returns every tag bit, shifted down to the low bits. Unlike [`Tag::get_tag`], this
+            /// is never truncated, even when there are more tag bits than `usize` holds
+            pub const fn tag_raw(self) -> $ty {
+                (self.0 & !Self::INDEX_MASK) >> INDEX_BITS
+            }
+        }
+
+        impl<const INDEX_BITS: usize> Tag for TagBits<$ty, INDEX_BITS> {
+            type IndexTy = $ty;
+            type Raw = $ty;
+
+            fn new(index: Self::IndexTy) -> Self {
+                Self(index & Self::INDEX_MASK)
+            }
+
+            fn get_index(&self) -> Self::IndexTy {
+                self.0 & Self::INDEX_MASK
+            }
+
+            fn set_index(&mut self, index: Self::IndexTy) {
+                self.0 = (index & Self::INDEX_MASK) | (self.0 & !Self::INDEX_MASK);
+            }
+
+            fn get_tag(&self) -> usize {
+                // Keeps the lowest `usize::BITS` tag bits, matching what `set_tag` can store
+                self.tag_raw() as usize
+            }
+
+            fn set_tag(&mut self, tag: usize) {
+                // Bits above `Self::TAG_BITS` are shifted out
+                self.0 = (self.0 & Self::INDEX_MASK) | ((tag as $ty) << INDEX_BITS);
+            }
+
+            fn tag_bits_count() -> usize {
+                Self::TAG_BITS
+            }
+
+            fn from_raw(raw: $ty) -> Self {
+                Self(raw)
+            }
+
+            fn to_raw(&self) -> $ty {
+                self.0
+            }
+        }
+    };
+}
+
+impl_tag_bits!(u32);
+impl_tag_bits!(u64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX_MASK_31: u32 = 0x7FFF_FFFF;
+
+    #[test]
+    fn tag() {
+        let mut tag: Tag31_1 = Tag::new(0);
+        assert_eq!(Tag31_1::tag_bits_count(), 1);
+        assert_eq!(tag.get_index(), 0);
+        assert_eq!(tag.get_tag(), 0);
+
+        tag.set_tag(1);
+        assert_eq!(tag.get_tag(), 1);
+
+        tag.set_tag(0);
+        assert_eq!(tag.get_tag(), 0);
+
+        tag.set_tag(3);
+        assert_eq!(tag.get_tag(), 1);
+
+        tag.set_index(0xFFFF_FFFF);
+        assert_eq!(tag.get_index(), INDEX_MASK_31);
+        assert_eq!(tag.get_tag(), 1);
+
+        tag.set_index(0);
+        assert_eq!(tag.get_index(), 0);
+        assert_eq!(tag.get_tag(), 1);
+    }
+
+    /// Checks the masks, boundary values, and that each setter preserves the other field
+    macro_rules! check_tag_bits {
+        ($ty:ty, $index_bits:expr) => {{
+            type T = TagBits<$ty, $index_bits>;
+            let tag_bits = <$ty>::BITS as usize - $index_bits;
+            let max_index: $ty = <$ty>::MAX >> tag_bits;
+            let max_raw_tag: $ty = <$ty>::MAX >> $index_bits;
+            // Truncated to the bits `get_tag` returns when the tag is wider than `usize`
+            let max_tag = max_raw_tag as usize;
+
+            assert_eq!(T::tag_bits_count(), tag_bits);
+            assert_eq!(T::INDEX_MASK, max_index);
+            assert_eq!(T::INDEX_MASK.count_ones() as usize, $index_bits);
+
+            let mut tag: T = Tag::new(0);
+            assert_eq!(tag.to_raw(), 0);
+            assert_eq!(tag.get_tag(), 0);
+
+            // New never sets tag bits
+            let tag_from_max: T = Tag::new(<$ty>::MAX);
+            assert_eq!(tag_from_max.get_index(), max_index);
+            assert_eq!(tag_from_max.get_tag(), 0);
+
+            for &index in [0, 1, max_index / 2, max_index - 1, max_index].iter() {
+                for &user_tag in [0, 1, max_tag - 1, max_tag].iter() {
+                    tag.set_index(index);
+                    tag.set_tag(user_tag);
+                    assert_eq!(tag.get_index(), index);
+                    assert_eq!(tag.get_tag(), user_tag);
+
+                    // Setting the tag keeps the index and vice versa
+                    tag.set_tag(max_tag - user_tag);
+                    assert_eq!(tag.get_index(), index);
+                    tag.set_index(max_index - index);
+                    assert_eq!(tag.get_tag(), max_tag - user_tag);
+                    assert_eq!(T::from_raw(tag.to_raw()).get_index(), max_index - index);
+                }
+            }
+
+            // Out of range values are truncated without touching the other field
+            tag.set_index(1);
+            tag.set_tag(max_tag.wrapping_add(1));
+            assert_eq!(tag.get_tag(), 0);
+            assert_eq!(tag.get_index(), 1);
+            tag.set_tag(max_tag);
+            tag.set_index(<$ty>::MAX);
+            assert_eq!(tag.get_index(), max_index);
+            assert_eq!(tag.get_tag(), max_tag);
+            assert_eq!(tag.tag_raw(), max_tag as $ty);
+
+            // Every tag bit is kept, including the highest, even if it is beyond `usize`
+            let full = T::from_raw(<$ty>::MAX);
+            assert_eq!(full.tag_raw(), max_raw_tag);
+            assert_eq!(full.get_tag(), max_tag);
+            assert_eq!(full.get_index(), max_index);
+            let high = T::from_raw(1 << (<$ty>::BITS - 1));
+            assert_eq!(high.tag_raw(), 1 << (tag_bits - 1));
+            assert_eq!(high.get_tag(), (1 as $ty << (tag_bits - 1)) as usize);
+            assert_eq!(high.get_index(), 0);
+        }};
+    }
+
+    #[test]
+    fn tag_bits_u32() {
+        check_tag_bits!(u32, 31);
+        check_tag_bits!(u32, 30);
+        check_tag_bits!(u32, 28);
+        check_tag_bits!(u32, 24);
+        check_tag_bits!(u32, 1);
+    }
+
+    #[test]
+    fn tag_bits_u64() {
+        check_tag_bits!(u64, 63);
+        check_tag_bits!(u64, 56);
+        check_tag_bits!(u64, 48);
+        check_tag_bits!(u64, 33);
+        // 40 tag bits, wider than `usize` on 32 bit targets
+        check_tag_bits!(u64, 24);
+    }
+
+    #[test]
+    fn aliases() {
+        assert_eq!(Tag31_1::INDEX_MASK, INDEX_MASK_31);
+        assert_eq!(Tag30_2::tag_bits_count(), 2);
+        assert_eq!(Tag28_4::tag_bits_count(), 4);
+        assert_eq!(Tag24_8::tag_bits_count(), 8);
+        assert_eq!(Tag56_8::tag_bits_count(), 8);
+        assert_eq!(Tag56_8::INDEX_MASK, 0x00FF_FFFF_FFFF_FFFF);
+    }
+}
@@ -0,0 +1,534 @@
+//! Opt-in statistics about which parts of a key are used for encryption.
+//!
+//! A cipher only records statistics when it is built with an observer, for example using
+//! [`Algorithm1::with_observer`](crate::alg1::Algorithm1::with_observer). Ciphers built with `new`
+//! use [`NoObserver`], which compiles away entirely.
+//!
+//! With the `std` feature, a [`UsageCounter`] can be exported as a [`UsageHistogram`] in CSV, JSON
+//! or PGM heatmap form, and summarized with [`UsageStats`] to audit how evenly an index hash
+//! spreads blocks over the key.
+//!
+//! ```
+//! use encryption::{Key, UsageCounter, alg1::{CipherBlock, Algorithm1}};
+//!
+//! let key = Key::new([7u8; 64]);
+//! // A 28 byte block from a 64 byte key has 10 possible word offsets
+//! let counter = UsageCounter::<10>::new();
+//! let cipher = Algorithm1::with_observer(&key, 0, &counter);
+//!
+//! let mut block = CipherBlock::new([0u8; 28]);
+//! for index in 0..20 {
+//!     cipher.cipher_block(index, &mut block);
+//! }
+//! assert_eq!(counter.total(), 20);
+//! ```
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Receives the word offset into the key of every subkey used to encrypt a block
+pub trait UsageObserver {
+    /// Called once per block with the offset (in words) of the first key word used
+    fn record(&self, word_offset: usize);
+}
+
+/// An observer that does nothing. Used by ciphers that are not observed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoObserver;
+
+impl UsageObserver for NoObserver {
+    #[inline(always)]
+    fn record(&self, _word_offset: usize) {}
+}
+
+impl<O: UsageObserver + ?Sized> UsageObserver for &O {
+    #[inline]
+    fn record(&self, word_offset: usize) {
+        (**self).record(word_offset)
+    }
+}
+
+/// Counts how often each word offset is used with lock free atomic counters.
+///
+/// Offsets of `SLOTS` or more are counted together in [`UsageCounter::overflow`]
+pub struct UsageCounter<const SLOTS: usize> {
+    counts: [AtomicUsize; SLOTS],
+    overflow: AtomicUsize,
+}
+
+impl<const SLOTS: usize> UsageCounter<SLOTS> {
+    /// Creates a counter with every count set to zero
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            counts: [ZERO; SLOTS],
+            overflow: ZERO,
+        }
+    }
+
+    /// Returns how many times `word_offset` was used
+    pub fn count(&self, word_offset: usize) -> usize {
+        match self.counts.get(word_offset) {
+            Some(count) => count.load(Ordering::Relaxed),
+            None => 0,
+        }
+    }
+
+    /// Returns the counts of each offset, starting at offset 0
+    pub fn counts(&self) -> impl Iterator<Item = usize> + '_ {
+        self.counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+    }
+
+    /// Returns how many offsets were recorded that did not fit in `SLOTS` counters
+    pub fn overflow(&self) -> usize {
+        self.overflow.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total number of recorded offsets, including overflowed ones
+    pub fn total(&self) -> usize {
+        self.counts().sum::<usize>() + self.overflow()
+    }
+
+    /// Sets every count back to zero
+    pub fn reset(&self) {
+        for count in &self.counts {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.overflow.store(0, Ordering::Relaxed);
+    }
+
+    /// Takes a snapshot of the counts for offsets `0..slots`, for export and analysis.
+    ///
+    /// `slots` should be the number of offsets the observed cipher can use, from its
+    /// `offset_count` method. Counts at or above `slots` are added to the overflow count
+    #[cfg(feature = "std")]
+    pub fn histogram(&self, slots: usize) -> UsageHistogram {
+        let slots = slots.min(SLOTS);
+        let counts: Vec<usize> = self.counts().collect();
+        let overflow = self.overflow() + counts[slots..].iter().sum::<usize>();
+        UsageHistogram {
+            counts: counts[..slots].to_vec(),
+            overflow,
+        }
+    }
+}
+
+impl<const SLOTS: usize> UsageObserver for UsageCounter<SLOTS> {
+    fn record(&self, word_offset: usize) {
+        let count = self.counts.get(word_offset).unwrap_or(&self.overflow);
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<const SLOTS: usize> Default for UsageCounter<SLOTS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A snapshot of how often each key offset was used, taken with [`UsageCounter::histogram`]
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageHistogram {
+    counts: Vec<usize>,
+    overflow: usize,
+}
+
+/// Summary statistics of a [`UsageHistogram`]
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UsageStats {
+    /// The number of offsets in the histogram
+    pub slots: usize,
+    /// The total number of recorded uses, not including overflow
+    pub total: usize,
+    /// The smallest count of any offset
+    pub min: usize,
+    /// The largest count of any offset
+    pub max: usize,
+    /// The mean count of all offsets
+    pub mean: f64,
+    /// `max / mean`. 1.0 means perfectly even use of the key
+    pub max_mean_ratio: f64,
+    /// Pearson's chi-square statistic against a uniform distribution
+    pub chi_square: f64,
+    /// The probability of a chi-square statistic at least this large if the offsets were
+    /// uniformly distributed. Very small values mean some parts of the key are favoured
+    pub p_value: f64,
+}
+
+#[cfg(feature = "std")]
+impl UsageHistogram {
+    /// Creates a histogram from the counts of offsets starting at 0
+    pub fn from_counts(counts: Vec<usize>) -> Self {
+        Self {
+            counts,
+            overflow: 0,
+        }
+    }
+
+    /// Returns the count of each offset, starting at offset 0
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    /// Returns the number of uses that fell outside of this histogram
+    pub fn overflow(&self) -> usize {
+        self.overflow
+    }
+
+    /// Computes summary statistics of this histogram.
+    ///
+    /// If the histogram is empty or nothing was recorded, the floating point statistics are NaN
+    pub fn stats(&self) -> UsageStats {
+        let slots = self.counts.len();
+        let total: usize = self.counts.iter().sum();
+        let min = self.counts.iter().copied().min().unwrap_or(0);
+        let max = self.counts.iter().copied().max().unwrap_or(0);
+        let mean = total as f64 / slots as f64;
+        let chi_square = self
+            .counts
+            .iter()
+            .map(|&count| {
+                let diff = count as f64 - mean;
+                diff * diff / mean
+            })
+            .sum::<f64>();
+        let p_value = if slots > 1 && total > 0 {
+            chi_square_p_value(chi_square, slots - 1)
+        } else {
+            f64::NAN
+        };
+
+        UsageStats {
+            slots,
+            total,
+            min,
+            max,
+            mean,
+            max_mean_ratio: max as f64 / mean,
+            chi_square,
+            p_value,
+        }
+    }
+
+    /// Writes this histogram as CSV with an `offset,count` header
+    pub fn write_csv(&self, mut w: impl std::io::Write) -> std::io::Result<()> {
+        writeln!(w, "offset,count")?;
+        for (offset, count) in self.counts.iter().enumerate() {
+            writeln!(w, "{},{}", offset, count)?;
+        }
+        Ok(())
+    }
+
+    /// Writes this histogram and its [`UsageStats`] as a JSON object.
+    /// Statistics that are NaN are written as `null`
+    pub fn write_json(&self, mut w: impl std::io::Write) -> std::io::Result<()> {
+        fn number(x: f64) -> String {
+            if x.is_finite() {
+                x.to_string()
+            } else {
+                "null".to_string()
+            }
+        }
+
+        let stats = self.stats();
+        write!(w, "{{\"counts\":[")?;
+        for (i, count) in self.counts.iter().enumerate() {
+            if i != 0 {
+                write!(w, ",")?;
+            }
+            write!(w, "{}", count)?;
+        }
+        write!(
+            w,
+            "],\"overflow\":{},\"stats\":{{\"slots\":{},\"total\":{},\"min\":{},\"max\":{},\
+            \"mean\":{},\"max_mean_ratio\":{},\"chi_square\":{},\"p_value\":{}}}}}",
+            self.overflow,
+            stats.slots,
+            stats.total,
+            stats.min,
+            stats.max,
+            number(stats.mean),
+            number(stats.max_mean_ratio),
+            number(stats.chi_square),
+            number(stats.p_value),
+        )
+    }
+
+    /// Writes this histogram as a binary (P5) grayscale PGM image, `width` offsets per row.
+    ///
+    /// Each pixel is one word offset of the key, with the most used offset white and unused
+    /// offsets black. Pixels past the last offset in the final row are black
+    ///
+    /// # Panics
+    /// If `width` is zero
+    pub fn write_pgm(&self, mut w: impl std::io::Write, width: usize) -> std::io::Result<()> {
+        assert_ne!(width, 0, "PGM width must be non zero");
+        let height = self.counts.len().div_ceil(width);
+        let max = self.counts.iter().copied().max().unwrap_or(0).max(1);
+
+        write!(w, "P5\n{} {}\n255\n", width, height)?;
+        let mut pixels: Vec<u8> = self
+            .counts
+            .iter()
+            .map(|&count| (count as u128 * 255 / max as u128) as u8)
+            .collect();
+        pixels.resize(width * height, 0);
+        w.write_all(&pixels)
+    }
+}
+
+/// Returns the upper tail probability of the chi-square distribution with `dof` degrees of freedom
+#[cfg(feature = "std")]
+fn chi_square_p_value(chi_square: f64, dof: usize) -> f64 {
+    upper_regularized_gamma(dof as f64 / 2.0, chi_square / 2.0)
+}
+
+/// Natural log of the gamma function using the Lanczos approximation (g = 7, n = 9)
+#[cfg(feature = "std")]
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // Reflection formula
+        let pi = core::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, c) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * core::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Q(a, x), the upper regularized incomplete gamma function
+#[cfg(feature = "std")]
+fn upper_regularized_gamma(a: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-15;
+    const MAX_ITERATIONS: usize = 1000;
+
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let log_prefix = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        // Series expansion of P(a, x)
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..MAX_ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        1.0 - sum * log_prefix.exp()
+    } else {
+        // Continued fraction for Q(a, x) using Lentz's method
+        let tiny = f64::MIN_POSITIVE / EPSILON;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for n in 1..MAX_ITERATIONS {
+            let an = -(n as f64) * (n as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        log_prefix.exp() * h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{alg1, alg2, Key};
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn counter() {
+        let counter = UsageCounter::<4>::new();
+        counter.record(0);
+        counter.record(3);
+        counter.record(3);
+        counter.record(4);
+        counter.record(100);
+        assert_eq!(counter.counts().collect::<Vec<_>>(), vec![1, 0, 0, 2]);
+        assert_eq!(counter.count(3), 2);
+        assert_eq!(counter.count(100), 0);
+        assert_eq!(counter.overflow(), 2);
+        assert_eq!(counter.total(), 5);
+
+        counter.reset();
+        assert_eq!(counter.total(), 0);
+    }
+
+    #[test]
+    fn observed_ciphers() {
+        let key = Key::new([0x5Au8; 256]);
+
+        let counter = UsageCounter::<64>::new();
+        let cipher = alg1::Algorithm1::with_observer(&key, 0, &counter);
+        let mut block = alg1::CipherBlock::new([0; 28]);
+        for index in 0..100 {
+            cipher.cipher_block(index, &mut block);
+        }
+        assert_eq!(counter.total(), 100);
+        assert_eq!(counter.overflow(), 0);
+
+        let counter = UsageCounter::<2>::new();
+        let cipher = alg2::Algorithm2::with_observer(&key, 0, &counter);
+        let mut block = alg2::CipherBlock::new([0; 248]);
+        cipher.cipher_block(0, &mut block);
+        assert_eq!(counter.total(), 1);
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        static COUNTER: UsageCounter<16> = UsageCounter::new();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    for offset in 0..32 {
+                        COUNTER.record(offset);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(COUNTER.total(), 128);
+        assert_eq!(COUNTER.overflow(), 64);
+        assert!(COUNTER.counts().all(|count| count == 4));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn p_values() {
+        // Critical values from a chi-square table
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-4;
+        assert!(close(chi_square_p_value(3.841, 1), 0.05));
+        assert!(close(chi_square_p_value(18.307, 10), 0.05));
+        assert!(close(chi_square_p_value(6.635, 1), 0.01));
+        assert!(close(chi_square_p_value(124.342, 100), 0.05));
+        assert!(close(chi_square_p_value(0.0, 5), 1.0));
+        assert!(close(ln_gamma(5.0), 24f64.ln()));
+        assert!(close(ln_gamma(0.5), core::f64::consts::PI.sqrt().ln()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn histogram_stats() {
+        let counter = UsageCounter::<8>::new();
+        for offset in [0, 0, 0, 1, 2, 3, 6, 7] {
+            counter.record(offset);
+        }
+        let histogram = counter.histogram(4);
+        assert_eq!(histogram.counts(), &[3, 1, 1, 1]);
+        assert_eq!(histogram.overflow(), 2);
+
+        let stats = histogram.stats();
+        assert_eq!(stats.slots, 4);
+        assert_eq!(stats.total, 6);
+        assert_eq!(stats.min, 1);
+        assert_eq!(stats.max, 3);
+        assert_eq!(stats.mean, 1.5);
+        assert_eq!(stats.max_mean_ratio, 2.0);
+        assert_eq!(stats.chi_square, 2.0);
+
+        let empty = UsageHistogram::from_counts(vec![0; 4]).stats();
+        assert!(empty.p_value.is_nan());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn export_formats() {
+        let histogram = UsageHistogram::from_counts(vec![4, 2, 0]);
+
+        let mut csv = Vec::new();
+        histogram.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "offset,count\n0,4\n1,2\n2,0\n"
+        );
+
+        let mut json = Vec::new();
+        histogram.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with("{\"counts\":[4,2,0],\"overflow\":0,\"stats\":{\"slots\":3,"));
+        assert!(json.ends_with("}}"));
+
+        let mut pgm = Vec::new();
+        histogram.write_pgm(&mut pgm, 2).unwrap();
+        assert_eq!(pgm, b"P5\n2 2\n255\n\xFF\x7F\x00\x00");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn audit_hash_spread() {
+        use rand::{RngCore, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        // Every offset of the key is audited, so Miri audits a smaller key
+        const KEY_BYTES: usize = 2048 / crate::test_step(4);
+        let mut key_bytes = [0u8; KEY_BYTES];
+        rng.fill_bytes(&mut key_bytes);
+        let key = Key::new(key_bytes);
+
+        // The identity hash with sequential indices walks the key, using every offset equally.
+        // In bit offset mode each word offset is used by 32 consecutive indices
+        let counter = UsageCounter::<512>::new();
+        let cipher = alg1::Algorithm1::with_observer(&key, 0, &counter);
+        let slots = cipher.offset_count();
+        let mut block = alg1::CipherBlock::new([0; 28]);
+        for index in 0..slots as u32 * 32 * 2 {
+            cipher.cipher_block(index, &mut block);
+        }
+        let stats = counter.histogram(slots).stats();
+        assert_eq!(stats.min, 64);
+        assert_eq!(stats.max, 64);
+        assert!(stats.p_value > 0.999);
+
+        // The S-Box hash of random indices should be indistinguishable from uniform
+        let counter = UsageCounter::<256>::new();
+        let cipher = alg2::Algorithm2::with_observer(&key, rng.next_u64(), &counter);
+        let slots = cipher.offset_count();
+        let mut block = alg2::CipherBlock::new([0; 248]);
+        for _ in 0..slots * 50 {
+            cipher.cipher_block(rng.next_u64(), &mut block);
+        }
+        let histogram = counter.histogram(slots);
+        assert_eq!(histogram.overflow(), 0);
+        let stats = histogram.stats();
+        assert!(stats.p_value > 1e-4, "{:?}", stats);
+        assert!(stats.max_mean_ratio < 1.5, "{:?}", stats);
+    }
+}
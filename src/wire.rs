@@ -0,0 +1,135 @@
+//! Endianness independent wire format for indexed blocks.
+//!
+//! `as_bytes` on an indexed block exposes its in memory representation, which uses the native
+//! byte order of the machine. The wire format instead stores the tag followed by every data word,
+//! each in little endian byte order, so senders and receivers with different native byte orders
+//! agree on the tag, index, and data words.
+//!
+//! The keystream Xored into the data words is read from the key in little endian word order too,
+//! so a block encrypted on a little endian sender decrypts on a big endian receiver
+
+use core::fmt;
+use core::mem::size_of;
+use core::ops::{Deref, DerefMut};
+
+use crate::Pod;
+
+/// Errors returned when parsing blocks from their wire format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireError {
+    /// The input was not exactly the size of a block on the wire
+    WrongLength { expected: usize, actual: usize },
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::WrongLength { expected, actual } => write!(
+                f,
+                "wrong length for block on the wire: expected {} bytes, got {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for WireError {}
+
+/// Returns Err unless `bytes` is exactly `expected` bytes long
+pub(crate) fn check_length(bytes: &[u8], expected: usize) -> Result<(), WireError> {
+    if bytes.len() == expected {
+        Ok(())
+    } else {
+        Err(WireError::WrongLength {
+            expected,
+            actual: bytes.len(),
+        })
+    }
+}
+
+/// The wire format of a value: a [`Pod`] value whose fields are already in little endian byte
+/// order, so its bytes are the same on every platform.
+///
+/// Dereferences to those bytes, so it is used like a byte array of the wire size
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct WireBytes<P>(pub(crate) P);
+
+impl<P: Pod> Deref for WireBytes<P> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        crate::pod::bytes_of(&self.0)
+    }
+}
+
+impl<P: Pod> DerefMut for WireBytes<P> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        crate::pod::bytes_of_mut(&mut self.0)
+    }
+}
+
+impl<P: Pod> AsRef<[u8]> for WireBytes<P> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<P: Pod> fmt::Debug for WireBytes<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<P: Pod, Q: Pod> PartialEq<WireBytes<Q>> for WireBytes<P> {
+    fn eq(&self, other: &WireBytes<Q>) -> bool {
+        **self == **other
+    }
+}
+
+impl<P: Pod> Eq for WireBytes<P> {}
+
+impl<P: Pod> PartialEq<[u8]> for WireBytes<P> {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+impl<P: Pod> PartialEq<WireBytes<P>> for [u8] {
+    fn eq(&self, other: &WireBytes<P>) -> bool {
+        *self == **other
+    }
+}
+
+impl<P: Pod, const N: usize> PartialEq<[u8; N]> for WireBytes<P> {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        **self == *other
+    }
+}
+
+/// Two values stored back to back without padding, for building wire formats out of several
+/// fields
+#[derive(Clone, Copy)]
+#[repr(C, packed)]
+pub struct Packed<A, B>(pub(crate) A, pub(crate) B);
+
+/// SAFETY: `Self` is `repr(C, packed)`, so it has no padding, and `NO_PADDING` checks that neither
+/// field has padding either
+unsafe impl<A: Pod, B: Pod> Pod for Packed<A, B> {
+    const NO_PADDING: () = {
+        #[allow(clippy::let_unit_value)]
+        let ((), ()) = (A::NO_PADDING, B::NO_PADDING);
+        assert!(size_of::<Self>() == size_of::<A>() + size_of::<B>());
+    };
+}
+
+/// Reads a word stored in little endian byte order from `bytes`, which has any alignment
+///
+/// # Panics
+/// If `bytes` is not exactly the size of `W`
+pub(crate) fn read_le<W: crate::Word>(bytes: &[u8]) -> W {
+    let mut word = W::from(0);
+    crate::pod::bytes_of_mut(&mut word).copy_from_slice(bytes);
+    W::from_le(word)
+}